@@ -1,13 +1,22 @@
-use std::collections::HashSet;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 use crate::utils::ParseError;
 
 type C = isize;
 type Coords = (C, C);
 
+// L and R turn by 90 degrees, l and r by 45 degrees, A turns around and F
+// keeps the current heading. Every command then walks the given number of
+// blocks, diagonal steps count as one block.
+#[derive(Debug, Clone, PartialEq)]
 enum Command {
     Right(usize),
     Left(usize),
+    HalfRight(usize),
+    HalfLeft(usize),
+    AboutFace(usize),
+    Forward(usize),
 }
 
 impl FromStr for Command {
@@ -16,17 +25,75 @@ impl FromStr for Command {
     fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
         use Command::*;
 
-        let first = s.trim().chars().next().ok_or(ParseError::new("Could not get first char"))?;
-        let len = s.trim().chars().skip(1).collect::<String>().parse::<usize>()?;
+        let s = s.trim();
+        let first = s.chars().next().ok_or(ParseError::new("Could not get first char"))?;
+        let len = s[first.len_utf8()..].parse::<usize>()
+            .map_err(|_| ParseError::new(&format!("Invalid number of blocks in command {}", s)))?;
 
         Ok(match first {
             'L' => Left(len),
             'R' => Right(len),
-            _ => Err(ParseError::new("Could not parse command"))?
+            'l' => HalfLeft(len),
+            'r' => HalfRight(len),
+            'A' => AboutFace(len),
+            'F' => Forward(len),
+            _ => Err(ParseError::new(&format!("Unknown command {}", s)))?
         })
     }
 }
 
+impl Command {
+    // clockwise turn in eighths of a full circle
+    fn turn(&self) -> usize {
+        use Command::*;
+
+        match self {
+            Forward(_) => 0,
+            HalfRight(_) => 1,
+            Right(_) => 2,
+            AboutFace(_) => 4,
+            Left(_) => 6,
+            HalfLeft(_) => 7,
+        }
+    }
+
+    fn len(&self) -> usize {
+        use Command::*;
+
+        match self {
+            Right(v) | Left(v) | HalfRight(v) | HalfLeft(v) | AboutFace(v) | Forward(v) => *v,
+        }
+    }
+}
+
+// One of the eight compass directions, clockwise starting at north
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Heading(usize);
+
+const HEADINGS: [(&str, Coords); 8] = [
+    ("N", (0, 1)), ("NE", (1, 1)), ("E", (1, 0)), ("SE", (1, -1)),
+    ("S", (0, -1)), ("SW", (-1, -1)), ("W", (-1, 0)), ("NW", (-1, 1)),
+];
+
+impl FromStr for Heading {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        HEADINGS.iter()
+            .position(|(name, _)| *name == s.trim())
+            .map(Heading)
+            .ok_or(ParseError::new(&format!("Unknown heading {}", s)))
+    }
+}
+
+impl Heading {
+    const NORTH: Heading = Heading(0);
+
+    fn dir(&self) -> Coords {
+        HEADINGS[self.0].1
+    }
+}
+
 #[aoc_generator(day1)]
 fn get_input(input: &str) -> Result<Vec<Command>, ParseError> {
     input
@@ -35,37 +102,382 @@ fn get_input(input: &str) -> Result<Vec<Command>, ParseError> {
         .collect::<Result<Vec<_>, ParseError>>()
 }
 
-fn rotate(dir: Coords, c: &Command) -> (Coords, usize) {
-    use Command::*;
+fn rotate(heading: Heading, c: &Command) -> (Heading, usize) {
+    (Heading((heading.0 + c.turn()) % 8), c.len())
+}
+
+// The four kinds of lines a segment can lie on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Family {
+    Horizontal,
+    Vertical,
+    Diagonal,
+    AntiDiagonal,
+}
+
+const FAMILIES: [Family; 4] = [Family::Horizontal, Family::Vertical, Family::Diagonal, Family::AntiDiagonal];
+
+impl Family {
+    fn of(dir: Coords) -> Self {
+        match dir {
+            (_, 0) => Family::Horizontal,
+            (0, _) => Family::Vertical,
+            (dx, dy) if dx == dy => Family::Diagonal,
+            _ => Family::AntiDiagonal,
+        }
+    }
+
+    // Lines of the family are a * x + b * y = c for some c
+    fn coefficients(&self) -> (C, C) {
+        match self {
+            Family::Horizontal => (0, 1),
+            Family::Vertical => (1, 0),
+            Family::Diagonal => (1, -1),
+            Family::AntiDiagonal => (1, 1),
+        }
+    }
+
+    // the c of the line through p
+    fn line(&self, p: Coords) -> C {
+        let (a, b) = self.coefficients();
+        a * p.0 + b * p.1
+    }
+
+    // position of p on its line, y for vertical lines and x for all others
+    fn along(&self, p: Coords) -> C {
+        if *self == Family::Vertical { p.1 } else { p.0 }
+    }
 
-    match (dir, c) {
-        ((0, 1), Right(v)) => ((1, 0), *v),
-        ((1, 0), Right(v)) => ((0, -1), *v),
-        ((0, -1), Right(v)) => ((-1, 0), *v),
-        ((-1, 0), Right(v)) => ((0, 1), *v),
+    fn point(&self, line: C, along: C) -> Coords {
+        match self {
+            Family::Horizontal => (along, line),
+            Family::Vertical => (line, along),
+            Family::Diagonal => (along, along - line),
+            Family::AntiDiagonal => (along, line - along),
+        }
+    }
 
-        ((0, 1), Left(v)) => ((-1, 0), *v),
-        ((-1, 0), Left(v)) => ((0, -1), *v),
-        ((0, -1), Left(v)) => ((1, 0), *v),
-        ((1, 0), Left(v)) => ((0, 1), *v),
-        _ => panic!("Oh no"),
+    // The grid point where lines of two different families meet, if any
+    fn intersect(&self, line: C, other: &Family, other_line: C) -> Option<Coords> {
+        let (a1, b1) = self.coefficients();
+        let (a2, b2) = other.coefficients();
+        let det = a1 * b2 - a2 * b1;
+        let x = line * b2 - b1 * other_line;
+        let y = a1 * other_line - a2 * line;
+
+        if det == 0 || x % det != 0 || y % det != 0 {
+            None
+        } else {
+            Some((x / det, y / det))
+        }
     }
 }
 
-#[aoc(day1, part1)]
-fn problem1(input: &Vec<Command>) -> Result<isize, ParseError> {
-    let mut pos = (0, 0);
-    let mut dir = (0, 1);
+// Straight piece of the walk, starting after `offset` blocks
+#[derive(Debug, Clone)]
+struct Segment {
+    start: Coords,
+    dir: Coords,
+    len: usize,
+    offset: usize,
+}
+
+impl Segment {
+    fn at(&self, t: usize) -> Coords {
+        let t = t as isize;
+        (self.start.0 + self.dir.0 * t, self.start.1 + self.dir.1 * t)
+    }
+
+    fn end(&self) -> Coords {
+        self.at(self.len)
+    }
+
+    fn family(&self) -> Family {
+        Family::of(self.dir)
+    }
+
+    fn line(&self) -> C {
+        self.family().line(self.start)
+    }
+
+    // lowest and highest position along the line
+    fn span(&self) -> (C, C) {
+        let family = self.family();
+        let (a, b) = (family.along(self.start), family.along(self.end()));
+        (min(a, b), max(a, b))
+    }
+
+    fn point(&self, along: C) -> Coords {
+        self.family().point(self.line(), along)
+    }
+
+    // lowest and highest value of the lines of another family through the
+    // segment's blocks
+    fn range_on(&self, family: Family) -> (C, C) {
+        let (a, b) = (family.line(self.start), family.line(self.end()));
+        (min(a, b), max(a, b))
+    }
+
+    // blocks walked from the start of the segment to p
+    fn steps_to(&self, p: Coords) -> usize {
+        max(p.0.abs_diff(self.start.0), p.1.abs_diff(self.start.1))
+    }
+}
 
-    for c in input {
-        let r = rotate(dir, c);
-        dir = r.0;
+// A stretch of the walk that covers ground already visited before, from and
+// to are both included. A single revisited block has from == to.
+#[derive(Debug, Clone, PartialEq)]
+struct Revisit {
+    from: Coords,
+    to: Coords,
+    // blocks walked when reaching `from`
+    distance: usize,
+}
+
+struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    fn walk(heading: Heading, commands: &[Command]) -> Self {
+        let mut pos = (0, 0);
+        let mut heading = heading;
+        let mut offset = 0;
+        let mut segments = vec![];
+
+        for c in commands {
+            let (h, len) = rotate(heading, c);
+            heading = h;
+
+            if len > 0 {
+                let segment = Segment { start: pos, dir: heading.dir(), len, offset };
+                pos = segment.end();
+                offset += len;
+                segments.push(segment);
+            }
+        }
+
+        Self { segments }
+    }
 
-        let len = r.1 as isize;
-        pos.0 += len * dir.0;
-        pos.1 += len * dir.1;
+    fn end(&self) -> Coords {
+        self.segments.last().map(|s| s.end()).unwrap_or((0, 0))
     }
 
+    #[allow(dead_code)]
+    fn distance(&self) -> usize {
+        self.segments.last().map(|s| s.offset + s.len).unwrap_or(0)
+    }
+
+    // All pairs of segments that share at least one block, with the shared
+    // blocks from p to q. Segments on lines of different families are found
+    // with a sweep line, collinear ones by sorting each line by start.
+    fn overlaps(&self) -> Vec<(usize, usize, Coords, Coords)> {
+        let mut overlaps = vec![];
+
+        for (i, a) in FAMILIES.iter().enumerate() {
+            for b in &FAMILIES[i + 1..] {
+                overlaps.append(&mut self.crossings(*a, *b));
+            }
+        }
+
+        let mut lines: HashMap<(Family, C), Vec<usize>> = HashMap::new();
+        for (i, s) in self.segments.iter().enumerate() {
+            lines.entry((s.family(), s.line())).or_default().push(i);
+        }
+
+        for line in lines.values_mut() {
+            line.sort_by_key(|i| self.segments[*i].span().0);
+
+            for (k, a) in line.iter().enumerate() {
+                let sa = &self.segments[*a];
+                let (_, a_end) = sa.span();
+
+                for b in &line[k + 1..] {
+                    let (b_start, b_end) = self.segments[*b].span();
+                    if b_start > a_end {
+                        break;
+                    }
+
+                    overlaps.push((*a, *b, sa.point(b_start), sa.point(min(a_end, b_end))));
+                }
+            }
+        }
+
+        overlaps
+    }
+
+    // Crossings between segments of two different families. Measured in
+    // lines of family b along one axis and lines of family a along the other,
+    // segments of a run parallel to the first axis and segments of b parallel
+    // to the second, so an axis aligned sweep finds all of them.
+    fn crossings(&self, a: Family, b: Family) -> Vec<(usize, usize, Coords, Coords)> {
+        let mut crossings = vec![];
+
+        // segments of a enter the sweep before and leave it after all
+        // segments of b at the same position are checked
+        let mut events = vec![];
+        for (i, s) in self.segments.iter().enumerate() {
+            if s.family() == a {
+                let (from, to) = s.range_on(b);
+                events.push((from, 0, i));
+                events.push((to, 2, i));
+            } else if s.family() == b {
+                events.push((s.line(), 1, i));
+            }
+        }
+        events.sort();
+
+        let mut active: BTreeMap<C, Vec<usize>> = BTreeMap::new();
+        for (_, kind, i) in events {
+            let s = &self.segments[i];
+            match kind {
+                0 => active.entry(s.line()).or_default().push(i),
+                2 => {
+                    if let Some(on_line) = active.get_mut(&s.line()) {
+                        on_line.retain(|h| *h != i);
+                    }
+                },
+                _ => {
+                    let (from, to) = s.range_on(a);
+                    for (line, segments) in active.range(from..=to) {
+                        // diagonal lines can cross between two blocks
+                        if let Some(p) = a.intersect(*line, &b, s.line()) {
+                            for h in segments {
+                                crossings.push((*h, i, p, p));
+                            }
+                        }
+                    }
+                },
+            }
+        }
+
+        crossings
+    }
+
+    // Every stretch of the walk that leads over blocks visited before, in the
+    // order they are walked. The starting block counts as visited.
+    fn revisits(&self) -> Vec<Revisit> {
+        let mut hits = vec![vec![]; self.segments.len()];
+
+        for (a, b, p, q) in self.overlaps() {
+            // a block is only revisited on the later segment, which has
+            // already been at its own first block when the previous one ended
+            let later = &self.segments[max(a, b)];
+            let (t1, t2) = (later.steps_to(p), later.steps_to(q));
+            let (t1, t2) = (max(min(t1, t2), 1), max(t1, t2));
+
+            if t1 <= t2 {
+                hits[max(a, b)].push((t1, t2));
+            }
+        }
+
+        let mut revisits = vec![];
+        for (segment, mut hits) in self.segments.iter().zip(hits) {
+            hits.sort();
+
+            let mut merged: Vec<(usize, usize)> = vec![];
+            for (t1, t2) in hits {
+                match merged.last_mut() {
+                    Some(last) if t1 <= last.1 + 1 => last.1 = max(last.1, t2),
+                    _ => merged.push((t1, t2)),
+                }
+            }
+
+            revisits.extend(merged.into_iter().map(|(t1, t2)| Revisit {
+                from: segment.at(t1),
+                to: segment.at(t2),
+                distance: segment.offset + t1,
+            }));
+        }
+
+        revisits
+    }
+
+    fn first_revisit(&self) -> Option<Coords> {
+        self.revisits().first().map(|r| r.from)
+    }
+}
+
+// Exports of the trajectory for a closer look at the route
+#[allow(dead_code)]
+impl Path {
+    // corners of the walk including start and end
+    fn vertices(&self) -> Vec<Coords> {
+        let mut vertices = vec![(0, 0)];
+        vertices.extend(self.segments.iter().map(|s| s.end()));
+        vertices
+    }
+
+    // every block in the order it is walked over, starting with the start
+    fn blocks(&self) -> impl Iterator<Item = Coords> + '_ {
+        std::iter::once((0, 0))
+            .chain(self.segments.iter().flat_map(|s| (1..=s.len).map(move |t| s.at(t))))
+    }
+
+    fn coordinate_list(&self) -> String {
+        self.blocks()
+            .map(|(x, y)| format!("{},{}\n", x, y))
+            .collect::<String>()
+    }
+
+    fn bounds(&self) -> (Coords, Coords) {
+        self.vertices().iter().fold(((0, 0), (0, 0)), |(lo, hi), p| {
+            ((min(lo.0, p.0), min(lo.1, p.1)), (max(hi.0, p.0), max(hi.1, p.1)))
+        })
+    }
+
+    // North is up. S marks the start, E the end, # blocks that were walked
+    // over once and X blocks that were visited more than once.
+    fn ascii_map(&self, max_size: usize) -> Result<String, ParseError> {
+        let ((x1, y1), (x2, y2)) = self.bounds();
+        let width = x1.abs_diff(x2) + 1;
+        let height = y1.abs_diff(y2) + 1;
+
+        if width.saturating_mul(height) > max_size {
+            Err(ParseError::new(&format!("Map of {}x{} blocks is too large", width, height)))?;
+        }
+
+        let mut visits = HashMap::new();
+        for p in self.blocks() {
+            *visits.entry(p).or_insert(0) += 1;
+        }
+
+        let end = self.end();
+        let mut map = String::new();
+        for y in (y1..=y2).rev() {
+            for x in x1..=x2 {
+                map.push(match ((x, y), visits.get(&(x, y))) {
+                    ((0, 0), _) => 'S',
+                    (p, _) if p == end => 'E',
+                    (_, Some(1)) => '#',
+                    (_, Some(_)) => 'X',
+                    _ => '.',
+                });
+            }
+            map.push('\n');
+        }
+
+        Ok(map)
+    }
+
+    fn svg(&self) -> String {
+        let ((x1, y1), (x2, y2)) = self.bounds();
+        let points = self.vertices().iter()
+            .map(|(x, y)| format!("{},{}", x, -y))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n  <polyline points=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"0.2\"/>\n</svg>\n",
+            x1 - 1, -y2 - 1, x2 - x1 + 2, y2 - y1 + 2, points)
+    }
+}
+
+#[aoc(day1, part1)]
+fn problem1(input: &Vec<Command>) -> Result<isize, ParseError> {
+    let pos = Path::walk(Heading::NORTH, input).end();
+
     Ok(pos.0.abs() + pos.1.abs())
 }
 
@@ -73,29 +485,131 @@ fn problem1(input: &Vec<Command>) -> Result<isize, ParseError> {
 // 156
 #[aoc(day1, part2)]
 fn problem2(input: &Vec<Command>) -> Result<isize, ParseError> {
-    let mut pos = (0, 0);
-    let mut dir = (0, 1);
+    let pos = Path::walk(Heading::NORTH, input).first_revisit()
+        .ok_or(ParseError::new("Could not find place that was visited twice"))?;
 
-    let mut visited = HashSet::new();
-    visited.insert(pos);
+    Ok(pos.0.abs() + pos.1.abs())
+}
 
-    for c in input {
-        let r = rotate(dir, c);
-        dir = r.0;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
 
-        let len = r.1 as isize;
+    // walks block by block and notes every block that was visited before
+    fn simulate(heading: Heading, commands: &[Command]) -> Vec<(usize, Coords)> {
+        let mut pos = (0, 0);
+        let mut heading = heading;
+        let mut distance = 0;
+        let mut visited = HashSet::new();
+        let mut revisits = vec![];
+        visited.insert(pos);
 
-        for _ in 0..len {
-            pos.0 += dir.0;
-            pos.1 += dir.1;
+        for c in commands {
+            let (h, len) = rotate(heading, c);
+            heading = h;
+            let dir = heading.dir();
 
-            if visited.contains(&pos) {
-                return Ok(pos.0.abs() + pos.1.abs())
+            for _ in 0..len {
+                pos = (pos.0 + dir.0, pos.1 + dir.1);
+                distance += 1;
+
+                if !visited.insert(pos) {
+                    revisits.push((distance, pos));
+                }
             }
-            visited.insert(pos);
         }
 
+        revisits
+    }
+
+    fn expand(revisits: &[Revisit]) -> Vec<(usize, Coords)> {
+        revisits.iter()
+            .flat_map(|r| {
+                let len = max(r.from.0.abs_diff(r.to.0), r.from.1.abs_diff(r.to.1));
+                let dir = ((r.to.0 - r.from.0).signum(), (r.to.1 - r.from.1).signum());
+                (0..=len).map(move |t| (r.distance + t, (r.from.0 + dir.0 * t as isize, r.from.1 + dir.1 * t as isize)))
+            })
+            .collect::<Vec<_>>()
+    }
+
+    fn random_commands(seed: u64, count: usize, kinds: u64) -> Vec<Command> {
+        let mut seed = seed;
+        let mut random = move |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+
+        (0..count)
+            .map(|_| {
+                let len = random(6) as usize;
+                match random(kinds) {
+                    0 => Command::Left(len),
+                    1 => Command::Right(len),
+                    2 => Command::Forward(len),
+                    3 => Command::AboutFace(len),
+                    4 => Command::HalfLeft(len),
+                    _ => Command::HalfRight(len),
+                }
+            })
+            .collect::<Vec<_>>()
+    }
+
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example"))?;
+        let path = Path::walk(Heading::NORTH, &input);
+
+        assert_eq!(Some((4, 0)), path.first_revisit());
+        assert_eq!(24, path.distance());
+        Ok(())
+    }
+
+    #[test]
+    pub fn matches_simulation() {
+        for seed in 0..200 {
+            let commands = random_commands(seed, 40, 2);
+            assert_eq!(simulate(Heading::NORTH, &commands), expand(&Path::walk(Heading::NORTH, &commands).revisits()));
+        }
+    }
+
+    #[test]
+    pub fn matches_simulation_with_diagonals() {
+        for seed in 0..300 {
+            let commands = random_commands(seed, 40, 6);
+            let heading = Heading(seed as usize % 8);
+            assert_eq!(simulate(heading, &commands), expand(&Path::walk(heading, &commands).revisits()));
+        }
+    }
+
+    #[test]
+    pub fn huge_steps() {
+        let commands = [Command::Right(4_000_000), Command::Left(3_000_000), Command::Left(2_000_000), Command::Left(6_000_000)];
+        let path = Path::walk(Heading::NORTH, &commands);
+
+        assert_eq!(Some((2_000_000, 0)), path.first_revisit());
+        assert_eq!(15_000_000, path.distance());
+    }
+
+    #[test]
+    pub fn parse() -> Result<(), ParseError> {
+        assert_eq!(vec![Command::Forward(2), Command::AboutFace(0), Command::HalfLeft(3)], get_input("F2, A0, l3")?);
+        assert_eq!(Heading(3), Heading::from_str("SE")?);
+        assert!(get_input("X2").is_err());
+        assert!(get_input("R").is_err());
+        assert!(Heading::from_str("Up").is_err());
+        Ok(())
     }
 
-    Err(ParseError::new("Could not find place that was visited twice"))
+    #[test]
+    pub fn exports() -> Result<(), ParseError> {
+        let path = Path::walk(Heading::from_str("E")?, &get_input("F2, L1, r1, A2")?);
+
+        assert_eq!(vec![(0, 0), (2, 0), (2, 1), (3, 2), (1, 0)], path.vertices());
+        assert_eq!("0,0\n1,0\n2,0\n2,1\n3,2\n2,1\n1,0\n", path.coordinate_list());
+        assert_eq!("...#\n..X.\nSE#.\n", path.ascii_map(100)?);
+        assert!(path.ascii_map(4).is_err());
+        assert!(path.svg().contains("points=\"0,0 2,0 2,-1 3,-2 1,0\""));
+        Ok(())
+    }
 }
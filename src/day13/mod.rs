@@ -1,73 +1,225 @@
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
 use crate::utils::ParseError;
-use pathfinding::prelude::dijkstra;
-use memoize::memoize;
+use pathfinding::prelude::astar;
 
 type C = isize;
 type Coords = (C, C);
 
-#[aoc_generator(day13)]
-fn get_input(input: &str) -> Result<isize, ParseError> {
-    Ok(input.parse::<isize>()?)
-}
+type Wall = Box<dyn Fn(Coords) -> bool>;
 
-#[memoize]
-fn is_open(p: Coords, c: isize) -> bool {
-    let x = p.0;
-    let y = p.1;
-    let v = x*x + 3*x + 2*x*y + y + y*y + c;
+const DEFAULT_CACHE_SIZE: usize = 1 << 16;
+// Longest path the puzzle searches for, the maze is infinite so there must be
+// some limit for destinations that are walled off
+const MAX_COST: usize = 10_000;
 
-    v.count_ones() % 2 == 0
+struct Maze {
+    is_wall: Wall,
+    cache: RefCell<HashMap<Coords, bool>>,
+    cache_size: usize,
 }
 
-fn neighbors(p: &Coords, c: isize) -> Vec<(Coords, usize)> {
-    let delta = [-1, 1];
-    let mut result = vec![];
+impl Maze {
+    fn new(is_wall: Wall) -> Self {
+        Self::with_cache_size(is_wall, DEFAULT_CACHE_SIZE)
+    }
+
+    fn with_cache_size(is_wall: Wall, cache_size: usize) -> Self {
+        let cache = RefCell::new(HashMap::new());
+
+        Self { is_wall, cache, cache_size }
+    }
 
-    for d in delta {
-        if is_open((p.0 + d, p.1), c) {
-            result.push((p.0 + d, p.1));
+    // The office building from the puzzle. Everything with negative coordinates
+    // is outside of the building and coordinates too large to evaluate the
+    // formula for are treated as walls as well.
+    fn office(favorite: C) -> Self {
+        Self::new(Box::new(move |p| office_wall(p, favorite)))
+    }
+
+    // Parses a literal map where '#' is a wall and every other char is open
+    // space. Everything outside of the map is a wall.
+    #[allow(dead_code)]
+    fn from_map(map: &str) -> Self {
+        let walls = map
+            .lines()
+            .map(|l| l.chars().map(|c| c == '#').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        Self::new(Box::new(move |(x, y)| {
+            if x < 0 || y < 0 {
+                return true;
+            }
+
+            walls.get(y as usize)
+                .and_then(|row| row.get(x as usize))
+                .cloned()
+                .unwrap_or(true)
+        }))
+    }
+
+    fn is_open(&self, p: Coords) -> bool {
+        if let Some(open) = self.cache.borrow().get(&p) {
+            return *open;
         }
 
-        if is_open((p.0, p.1 + d), c) {
-            result.push((p.0, p.1 + d));
+        let open = !(self.is_wall)(p);
+
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= self.cache_size {
+            cache.clear();
+        }
+        cache.insert(p, open);
+
+        open
+    }
+
+    fn neighbors(&self, p: &Coords) -> Vec<Coords> {
+        let candidates = [
+            p.0.checked_sub(1).map(|x| (x, p.1)),
+            p.0.checked_add(1).map(|x| (x, p.1)),
+            p.1.checked_sub(1).map(|y| (p.0, y)),
+            p.1.checked_add(1).map(|y| (p.0, y)),
+        ];
+
+        candidates.into_iter()
+            .flatten()
+            .filter(|n| self.is_open(*n))
+            .collect::<Vec<_>>()
+    }
+
+    // Shortest path of at most `max_cost` steps. Such a path can only visit
+    // points whose detour via start and destination is within `max_cost`, so
+    // the search stays in a finite area even if the destination can't be
+    // reached at all.
+    fn shortest_path(&self, start: Coords, destination: Coords, max_cost: usize) -> Option<(Vec<Coords>, usize)> {
+        if !self.is_open(start) || !self.is_open(destination) {
+            return None;
+        }
+
+        astar(
+            &start,
+            |p| self.neighbors(p)
+                .into_iter()
+                .filter(|n| manhattan(&start, n).saturating_add(manhattan(n, &destination)) <= max_cost)
+                .map(|n| (n, 1)),
+            |p| manhattan(p, &destination),
+            |p| *p == destination)
+            .filter(|(_, cost)| *cost <= max_cost)
+    }
+
+    fn reachable(&self, start: Coords, max_steps: usize) -> HashSet<Coords> {
+        let mut seen = HashSet::new();
+        if !self.is_open(start) {
+            return seen;
+        }
+
+        let mut queue = VecDeque::new();
+        seen.insert(start);
+        queue.push_back((start, 0));
+
+        while let Some((p, steps)) = queue.pop_front() {
+            if steps == max_steps {
+                continue;
+            }
+
+            for n in self.neighbors(&p) {
+                if seen.insert(n) {
+                    queue.push_back((n, steps + 1));
+                }
+            }
         }
+
+        seen
+    }
+}
+
+fn office_wall(p: Coords, c: C) -> bool {
+    if p.0 < 0 || p.1 < 0 {
+        return true;
     }
 
-    result.into_iter()
-        .filter(|p| p.0 >= 0 && p.1 >= 0)
-        .map(|p| (p, 1))
-        .collect::<Vec<_>>()
+    let x = p.0 as i128;
+    let y = p.1 as i128;
+
+    // x*x + 3*x + 2*x*y + y + y*y + c, rearranged to (x + y)^2 + 3*x + y + c
+    let v = (x + y).checked_mul(x + y)
+        .and_then(|v| v.checked_add(3 * x + y))
+        .and_then(|v| v.checked_add(c as i128));
+
+    match v {
+        Some(v) if v >= 0 => v.count_ones() % 2 == 1,
+        _ => true,
+    }
+}
+
+fn manhattan(a: &Coords, b: &Coords) -> usize {
+    a.0.abs_diff(b.0) + a.1.abs_diff(b.1)
+}
+
+#[aoc_generator(day13)]
+fn get_input(input: &str) -> Result<isize, ParseError> {
+    Ok(input.trim().parse::<isize>()?)
 }
 
 #[aoc(day13, part1)]
 fn problem1(input: &isize) -> Result<usize, ParseError> {
+    let maze = Maze::office(*input);
     let start = (1, 1);
     let destination = (31, 39);
-    let shortest = dijkstra(&start, |p| neighbors(p, *input), |p| *p == destination).ok_or(ParseError::new("Could not find shortest path"))?;
+    let shortest = maze.shortest_path(start, destination, MAX_COST).ok_or(ParseError::new("Could not find shortest path"))?;
 
     Ok(shortest.1)
 }
 
 #[aoc(day13, part2)]
 fn problem2(input: &isize) -> Result<usize, ParseError> {
-    let mut in_range = HashSet::new();
+    let maze = Maze::office(*input);
+    let start = (1, 1);
 
-    for y in 0..50 {
-        for x in 0..50 {
-            if !is_open((x, y), *input) {
-                continue
-            }
+    Ok(maze.reachable(start, 50).len())
+}
 
-            let start = (1, 1);
-            let destination = (x, y);
-            if let Some(shortest) = dijkstra(&start, |p| neighbors(p, *input), |p| *p == destination) {
-                if shortest.1 <= 50 {
-                    in_range.insert((x, y));
-                }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn example_1() {
+        let maze = Maze::office(10);
+        assert_eq!(Some(11), maze.shortest_path((1, 1), (7, 4), MAX_COST).map(|s| s.1));
+    }
+
+    #[test]
+    pub fn example_map() {
+        let maze = Maze::from_map(".#.####.##\n..#..#...#\n#....##...\n###.#.###.\n.##..#..#.\n..##....#.\n#...##.###");
+        let office = Maze::office(10);
+
+        for y in 0..7 {
+            for x in 0..10 {
+                assert_eq!(office.is_open((x, y)), maze.is_open((x, y)));
             }
         }
+        assert!(!maze.is_open((-1, 0)));
+        assert!(!maze.is_open((10, 0)));
     }
 
-    Ok(in_range.len())
+    #[test]
+    pub fn unbounded() {
+        let maze = Maze::with_cache_size(Box::new(|p| p.0 == 0 && p.1 != 0), 4);
+        assert_eq!(Some(6), maze.shortest_path((-2, -3), (1, 0), MAX_COST).map(|s| s.1));
+        assert!(maze.cache.borrow().len() <= 4);
+        assert!(!Maze::office(10).is_open((isize::MAX, isize::MAX)));
+    }
+
+    #[test]
+    pub fn unreachable() {
+        assert_eq!(None, Maze::office(10).shortest_path((1, 1), (1, 0), MAX_COST));
+        assert_eq!(None, Maze::office(10).shortest_path((1, 1), (7, 4), 10));
+
+        // an open point in the middle of a ring of walls
+        let maze = Maze::new(Box::new(|(x, y)| x.abs_diff(5).max(y.abs_diff(5)) == 1));
+        assert_eq!(None, maze.shortest_path((0, 0), (5, 5), 1000));
+        assert_eq!(Some(12), maze.shortest_path((0, 0), (4, 8), 1000).map(|s| s.1));
+    }
 }
@@ -1,124 +1,315 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use crate::utils::ParseError;
 use rayon::prelude::*;
 
-fn generate(salt: &str, index: usize) -> String {
-    let str = format!("{}{}", salt, index);
-    format!("{:x}", md5::compute(str.as_bytes()))
-}
+// A key is confirmed by a quintuple in any of the next 1000 hashes
+const LOOKAHEAD: usize = 1000;
+// Number of hashes computed in parallel whenever the buffer runs dry
+const CHUNK_SIZE: usize = 1024;
+// Extra rounds of hashing for part 2
+const STRETCH: usize = 2016;
+// Where computed hashes are kept between runs
+const CACHE_DIR: &str = "cache";
+// Number of chunks generated between two saves of the cache
+const SAVE_INTERVAL: usize = 16;
 
-fn generate_stretched(salt: &str, index: usize) -> String {
+// Hashes salt and index, then rehashes the hex digest `stretch` more times
+fn generate(salt: &str, index: usize, stretch: usize) -> String {
     let str = format!("{}{}", salt, index);
 
     let mut hash = md5::compute(str.as_bytes());
 
-    for _ in 0..2016 {
+    for _ in 0..stretch {
         hash = md5::compute(format!("{:x}", hash));
     }
 
     format!("{:x}", hash)
 }
 
+fn cache_file(dir: &Path, salt: &str, stretch: usize) -> PathBuf {
+    dir.join(format!("day14-{}-{}.txt", salt, stretch))
+}
+
+fn hex_value(c: u8) -> usize {
+    match c {
+        b'0'..=b'9' => (c - b'0') as usize,
+        _ => (c - b'a') as usize + 10,
+    }
+}
+
+#[derive(Debug)]
+struct Hash {
+    hash: String,
+    triple: Option<usize>,
+    // bit i is set if the hash contains five of hex digit i in a row
+    quintuples: u16,
+}
+
+impl Hash {
+    fn new(hash: String) -> Self {
+        let bytes = hash.as_bytes();
+
+        let triple = bytes.windows(3)
+            .find(|w| w[0] == w[1] && w[1] == w[2])
+            .map(|w| hex_value(w[0]));
+
+        let quintuples = bytes.windows(5)
+            .filter(|w| w.iter().all(|c| *c == w[0]))
+            .fold(0, |q, w| q | 1 << hex_value(w[0]));
+
+        Self { hash, triple, quintuples }
+    }
+}
+
+fn count_quintuples(counts: &mut [usize; 16], hash: &Hash, add: bool) {
+    for (digit, count) in counts.iter_mut().enumerate() {
+        if hash.quintuples & 1 << digit != 0 {
+            if add {
+                *count += 1;
+            } else {
+                *count -= 1;
+            }
+        }
+    }
+}
+
 struct KeyGen {
     salt: String,
-    v2: bool,
-    cache: HashMap<usize, String>,
+    stretch: usize,
+    // every hash computed or loaded so far, in index order, only kept if
+    // there is a file to save them to
+    cache: Vec<String>,
+    cache_file: Option<PathBuf>,
+    chunks_since_save: usize,
+    // the first error of a save in between, reported by the next `save`
+    save_error: Option<io::Error>,
+    // hashes computed ahead of time, not yet part of the window
+    pending: VecDeque<Hash>,
+    next_to_generate: usize,
+    // the current candidate followed by up to LOOKAHEAD hashes
+    window: VecDeque<Hash>,
+    index: usize,
+    // number of hashes behind the candidate containing a quintuple of each digit
+    quintuples: [usize; 16],
 }
 
 impl KeyGen {
-    fn new(salt: &str) -> Self {
-        let cache = HashMap::new();
+    fn new(salt: &str, stretch: usize) -> Self {
         let salt = salt.to_string();
-        let v2 = false;
 
-        Self { cache, v2, salt }
+        Self {
+            salt,
+            stretch,
+            cache: vec![],
+            cache_file: None,
+            chunks_since_save: 0,
+            save_error: None,
+            pending: VecDeque::new(),
+            next_to_generate: 0,
+            window: VecDeque::new(),
+            index: 0,
+            quintuples: [0; 16],
+        }
     }
 
-    fn v2_from_cache(salt: &str, map: HashMap<usize, String>) -> Self {
-        let salt = salt.to_string();
-        let v2 = true;
+    // Loads previously computed hashes for this salt and stretch count from
+    // `dir`, if there are any. Use `save` to write them back.
+    fn with_cache(salt: &str, stretch: usize, dir: &Path) -> io::Result<Self> {
+        let file = cache_file(dir, salt, stretch);
+        let mut gen = Self::new(salt, stretch);
 
-        Self { cache: map, v2, salt }
+        if file.exists() {
+            gen.cache = fs::read_to_string(&file)?
+                .lines()
+                .take_while(|l| l.len() == 32 && l.chars().all(|c| c.is_ascii_hexdigit()))
+                .map(|l| l.to_string())
+                .collect::<Vec<_>>();
+        }
+        gen.cache_file = Some(file);
+
+        Ok(gen)
     }
 
-    fn get(&mut self, index: usize) -> String {
-        let salt = self.salt.as_str();
-        let v2 = self.v2;
+    fn save(&mut self) -> io::Result<()> {
+        if let Some(e) = self.save_error.take() {
+            return Err(e);
+        }
+        self.chunks_since_save = 0;
 
-        if let Some(hash) = self.cache.get(&index) {
-            hash.to_string()
-        } else {
-            self.cache.entry(index).or_insert(if v2 { generate_stretched(salt, index) } else { generate(salt, index) }).to_string()
+        if let Some(file) = &self.cache_file {
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            let mut content = self.cache.join("\n");
+            content.push('\n');
+            fs::write(file, content)?;
         }
+
+        Ok(())
     }
-}
 
-fn is_key(gen: &mut KeyGen, index: usize) -> bool {
-    let hash = gen.get(index);
-    let mut c = None;
+    fn generate_chunk(&mut self) {
+        let start = self.next_to_generate;
+        let salt = self.salt.as_str();
+        let stretch = self.stretch;
+        let compute = |start: usize| (start..start + CHUNK_SIZE)
+            .into_par_iter()
+            .map(|i| generate(salt, i, stretch))
+            .collect::<Vec<_>>();
 
-    for window in hash.chars().collect::<Vec<_>>().windows(3) {
-        if window[0] == window[1] && window[1] == window[2] {
-            c = Some(window[0]);
-            break;
+        // without a cache file only the window is kept in memory
+        if self.cache_file.is_none() {
+            self.pending.extend(compute(start).into_iter().map(Hash::new));
+            self.next_to_generate = start + CHUNK_SIZE;
+            return;
+        }
+
+        if start >= self.cache.len() {
+            let mut chunk = compute(start);
+            self.cache.append(&mut chunk);
+
+            self.chunks_since_save += 1;
+            if self.chunks_since_save >= SAVE_INTERVAL {
+                if let Err(e) = self.save() {
+                    self.save_error.get_or_insert(e);
+                }
+            }
         }
-    }
 
-    if c.is_none() {
-        return false;
+        let end = self.cache.len().min(start + CHUNK_SIZE);
+        self.pending.extend(self.cache[start..end].iter().map(|h| Hash::new(h.clone())));
+        self.next_to_generate = end;
     }
 
-    let c = c.unwrap();
-    for i in 1..=1000 {
-        let hash = gen.get(index + i);
-        for window in hash.chars().collect::<Vec<_>>().windows(5) {
-            if window[0] == c && window[0] == window[1] && window[1] == window[2] && window[2] == window[3] && window[3] == window[4] {
-                return true;
+    fn fill_window(&mut self) {
+        while self.window.len() <= LOOKAHEAD {
+            if self.pending.is_empty() {
+                self.generate_chunk();
+            }
+
+            if let Some(hash) = self.pending.pop_front() {
+                // the first hash in the window is the candidate itself and
+                // must not confirm its own triple
+                if !self.window.is_empty() {
+                    count_quintuples(&mut self.quintuples, &hash, true);
+                }
+                self.window.push_back(hash);
             }
         }
     }
 
-    false
-}
+    // Advances the window by one, returns the index and hash that left the
+    // window if it is a key.
+    fn step(&mut self) -> Option<(usize, String)> {
+        self.fill_window();
 
-#[aoc(day14, part1)]
-fn problem1(input: &str) -> Result<usize, ParseError> {
-    let mut gen = KeyGen::new(input);
-    let mut number_of_keys = 0;
+        let candidate = self.window.pop_front()?;
+        let index = self.index;
+        self.index += 1;
+
+        let is_key = candidate.triple.map(|digit| self.quintuples[digit] > 0).unwrap_or(false);
+
+        // the new candidate no longer counts towards its own lookahead
+        if let Some(front) = self.window.front() {
+            count_quintuples(&mut self.quintuples, front, false);
+        }
 
-    for index in 0.. {
-        if is_key(&mut gen, index) {
-            number_of_keys += 1;
+        if is_key {
+            Some((index, candidate.hash))
+        } else {
+            None
         }
+    }
+}
+
+impl Iterator for KeyGen {
+    type Item = (usize, String);
 
-        if number_of_keys == 64 {
-            return Ok(index);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(key) = self.step() {
+                return Some(key);
+            }
         }
     }
+}
 
-    Ok(0)
+#[aoc(day14, part1)]
+fn problem1(input: &str) -> Result<usize, ParseError> {
+    let (index, _) = KeyGen::new(input, 0).nth(63).ok_or(ParseError::new("Could not find 64 keys"))?;
+    Ok(index)
 }
 
 #[aoc(day14, part2)]
 fn problem2(input: &str) -> Result<usize, ParseError> {
-    // upper limit determined by wrong solution that ran a lot longer :/
-    let keys = (0..22500).collect::<Vec<_>>();
-    let map = keys
-        .par_iter()
-        .map(|i| (*i, generate_stretched(input, *i)))
-        .collect::<HashMap<_, _>>();
-    let mut gen = KeyGen::v2_from_cache(input, map);
-
-    let mut number_of_keys = 0;
-    for index in 0.. {
-        if is_key(&mut gen, index) {
-            number_of_keys += 1;
-        }
+    let mut gen = KeyGen::with_cache(input, STRETCH, Path::new(CACHE_DIR))
+        .map_err(|e| ParseError::new(&format!("Could not read hash cache: {}", e)))?;
+    let key = gen.nth(63);
+    gen.save().map_err(|e| ParseError::new(&format!("Could not write hash cache: {}", e)))?;
+    let (index, _) = key.ok_or(ParseError::new("Could not find 64 keys"))?;
+
+    Ok(index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn example_1() {
+        let mut gen = KeyGen::new("abc", 0);
+        assert_eq!(Some(39), gen.next().map(|k| k.0));
+        assert_eq!(Some(92), gen.next().map(|k| k.0));
+        assert_eq!(Some(22728), gen.nth(61).map(|k| k.0));
+
+        // without a cache file nothing but the window is kept
+        assert!(gen.cache.is_empty());
+        assert!(gen.window.len() + gen.pending.len() <= LOOKAHEAD + CHUNK_SIZE + 1);
+    }
+
+    #[test]
+    pub fn example_2() {
+        assert!(generate("abc", 0, STRETCH).starts_with("a107ff"));
+    }
 
-        if number_of_keys == 64 {
-            return Ok(index);
+    #[test]
+    pub fn cache_roundtrip() -> io::Result<()> {
+        let dir = std::env::temp_dir().join("y2016-day14-test");
+        let file = cache_file(&dir, "abc", 1);
+        if file.exists() {
+            fs::remove_file(&file)?;
         }
+
+        let mut gen = KeyGen::with_cache("abc", 1, &dir)?;
+        let first = gen.next();
+        gen.save()?;
+
+        let mut cached = KeyGen::with_cache("abc", 1, &dir)?;
+        assert_eq!(gen.cache, cached.cache);
+        assert_eq!(first, cached.next());
+
+        fs::remove_file(&file)
     }
 
-    Ok(0)
+    #[test]
+    pub fn periodic_save() -> io::Result<()> {
+        let dir = std::env::temp_dir().join("y2016-day14-periodic-test");
+        let file = cache_file(&dir, "abc", 0);
+        if file.exists() {
+            fs::remove_file(&file)?;
+        }
+
+        // the cache is written while generating, without an explicit save
+        let mut gen = KeyGen::with_cache("abc", 0, &dir)?;
+        for _ in 0..SAVE_INTERVAL {
+            gen.generate_chunk();
+        }
+        assert_eq!(SAVE_INTERVAL * CHUNK_SIZE, KeyGen::with_cache("abc", 0, &dir)?.cache.len());
+
+        fs::remove_file(&file)
+    }
 }
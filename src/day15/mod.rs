@@ -1,66 +1,92 @@
+use regex::Regex;
+use std::str::FromStr;
 use crate::utils::ParseError;
+use crate::utils::crt::crt;
 
 #[derive(Debug, Clone)]
-struct Crt {
-    remainder: usize,
-    modulus: usize,
+struct Disc {
+    number: usize,
+    positions: usize,
+    start: usize,
 }
 
-impl Crt {
-    fn new(remainder: isize, modulus: usize) -> Self {
-        let remainder = (remainder % (modulus as isize) + modulus as isize) as usize;
+impl Disc {
+    fn new(number: usize, positions: usize, start: usize) -> Self {
+        Self { number, positions, start }
+    }
 
-        Self { remainder, modulus }
+    // The capsule reaches disc #n at time t + n and needs the disc to be at
+    // position 0 by then.
+    fn to_congruence(&self) -> (i128, i128) {
+        (-(self.start as i128) - self.number as i128, self.positions as i128)
     }
 }
 
-// stolen from my 2020/13 solution
-fn chinese_remainder(crts: &Vec<Crt>) -> usize {
-    // search the solution with the chinese remainder theorem
-    // https://en.wikipedia.org/wiki/Chinese_remainder_theorem#Search_by_sieving
+impl FromStr for Disc {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        lazy_static!{
+            static ref RE: Regex = Regex::new(r"^Disc #(?P<number>\d+) has (?P<positions>\d+) positions; at time=0, it is at position (?P<start>\d+)\.$").unwrap();
+        }
 
-    let mut time = crts[0].remainder;
-    let mut increment = crts[0].modulus;
+        let (number, positions, start) = RE.captures(s.trim()).and_then(|cap| {
+            let number = cap.name("number").map(|v| v.as_str().parse::<usize>())?.ok()?;
+            let positions = cap.name("positions").map(|v| v.as_str().parse::<usize>())?.ok()?;
+            let start = cap.name("start").map(|v| v.as_str().parse::<usize>())?.ok()?;
 
-    for crt in crts.iter().skip(1) {
-        loop {
-            if time % crt.modulus == crt.remainder {
-                break;
-            }
+            Some((number, positions, start))
+        }).ok_or(ParseError::new(&format!("Error parsing disc {}", s)))?;
 
-            time += increment;
+        if positions == 0 {
+            Err(ParseError::new(&format!("Disc #{} has no positions", number)))?;
         }
 
-        increment *= crt.modulus;
+        Ok(Self::new(number, positions, start))
     }
-
-    time
 }
 
 #[aoc_generator(day15)]
-fn get_input(_input: &str) -> Result<Vec<Crt>, ParseError> {
-    let start = [0, 0, 2, 2, 0, 7];
-    let modulus = [7, 13, 3, 5, 17, 19];
-
-    Ok(start.into_iter().enumerate()
-        .map(|(i, v)| {
-            let start = -v - (i as isize + 1);
-            Crt::new(start, modulus[i])
-        })
-        .collect::<Vec<_>>())
+fn get_input(input: &str) -> Result<Vec<Disc>, ParseError> {
+    input
+        .lines()
+        .map(Disc::from_str)
+        .collect::<Result<Vec<_>, ParseError>>()
+}
+
+fn first_time(discs: &[Disc]) -> Result<usize, ParseError> {
+    if discs.is_empty() {
+        Err(ParseError::new("No discs given"))?;
+    }
+
+    let congruences = discs.iter().map(|d| d.to_congruence()).collect::<Vec<_>>();
+    let (time, _) = crt(&congruences).map_err(|e| ParseError::new(&format!("Discs never align: {}", e)))?;
+
+    usize::try_from(time).map_err(|_| ParseError::new("Time does not fit into usize"))
 }
 
 #[aoc(day15, part1)]
-fn problem1(input: &Vec<Crt>) -> Result<usize, ParseError> {
-    let time = chinese_remainder(input);
-    Ok(time)
+fn problem1(input: &[Disc]) -> Result<usize, ParseError> {
+    first_time(input)
 }
 
 #[aoc(day15, part2)]
-fn problem2(input: &Vec<Crt>) -> Result<usize, ParseError> {
-    let mut input = input.clone();
-    input.push(Crt::new(-7, 11));
+fn problem2(input: &[Disc]) -> Result<usize, ParseError> {
+    let mut input = input.to_vec();
+    let number = input.iter().map(|d| d.number).max().unwrap_or(0) + 1;
+    input.push(Disc::new(number, 11, 0));
+
+    first_time(&input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    let time = chinese_remainder(&input);
-    Ok(time)
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example"))?;
+        assert_eq!(5, problem1(&input)?);
+        Ok(())
+    }
 }
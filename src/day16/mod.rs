@@ -1,50 +1,156 @@
 use crate::utils::ParseError;
+use crate::utils::bitvec::BitVec;
 
 #[aoc_generator(day16)]
-fn get_input(input: &str) -> Result<Vec<char>, ParseError> {
-    Ok(input.chars().collect::<Vec<_>>())
+fn get_input(input: &str) -> Result<BitVec, ParseError> {
+    BitVec::parse(input.trim(), '0', '1')
 }
 
-fn extend(input: &Vec<char>, size: usize) -> Vec<char> {
-    let mut extended = input.clone();
+// The extended data has the shape
+//
+//    a d0 b d1 a d2 b d3 a ...
+//
+// where b is a reversed and inverted and d0 d1 d2 ... are the joining bits
+// from the dragon curve. This is enough to count the ones in any prefix of the
+// data without ever writing it down.
+struct Dragon {
+    a: BitVec,
+    b: BitVec,
+}
+
+impl Dragon {
+    fn new(seed: &BitVec) -> Result<Self, ParseError> {
+        if seed.is_empty() {
+            Err(ParseError::new("Initial state is empty"))?;
+        }
 
-    while extended.len() < size {
-        let mut b = extended.clone().into_iter().rev().map(|v| {
-            if v == '0' { '1' } else { '0' }
-        }).collect::<Vec<_>>();
-        extended.push('0');
-        extended.append(&mut b);
+        let a = seed.clone();
+        let mut b = seed.clone();
+        b.reverse();
+        b.invert();
+
+        Ok(Self { a, b })
     }
 
-    extended
-}
+    // Number of ones among the first n joining bits. The j-th joining bit
+    // (starting at 1) is one if the odd part of j is 3 mod 4.
+    fn joiner_ones(n: u64) -> u64 {
+        let mut ones = 0;
+        let mut k = n;
+
+        while k > 0 {
+            ones += (k + 1) / 4;
+            k >>= 1;
+        }
+
+        ones
+    }
+
+    // Number of ones in the first n bits of the extended data
+    fn ones(&self, n: u64) -> u64 {
+        let len = self.a.len() as u64;
 
-fn checksum(input: &Vec<char>, size: usize) -> Vec<char> {
-    let mut checksum = input.iter().take(size).cloned().collect::<Vec<_>>();
+        let blocks = n / (len + 1);
+        let rest = (n % (len + 1)) as usize;
 
-    while checksum.len() % 2 == 0 {
-        let mut next = vec![];
-        for pair in checksum.chunks(2) {
-            next.push(if pair[0] == pair[1] { '1' } else { '0' });
+        // every pair of a and b contains exactly len ones
+        let ones = (blocks / 2) * len + Self::joiner_ones(blocks);
+
+        if blocks.is_multiple_of(2) {
+            ones + self.a.count_ones_before(rest) as u64
+        } else {
+            ones + (self.a.count_ones() + self.b.count_ones_before(rest)) as u64
         }
-        checksum = next;
     }
 
-    checksum
+    // Each digit of the checksum covers a chunk of the largest power of two
+    // that divides the size. The digit is 1 if the chunk holds an even number
+    // of ones, unless the data is not reduced at all.
+    fn checksum(&self, size: u64) -> Result<String, ParseError> {
+        if size == 0 {
+            Err(ParseError::new("Disk size must not be 0"))?;
+        }
+
+        let chunk = 1 << size.trailing_zeros();
+        let reduced = chunk > 1;
+
+        let mut checksum = String::with_capacity((size / chunk) as usize);
+        let mut before = 0;
+        for end in (chunk..=size).step_by(chunk as usize) {
+            let after = self.ones(end);
+            let even = (after - before).is_multiple_of(2);
+            checksum.push(if even == reduced { '1' } else { '0' });
+            before = after;
+        }
+
+        Ok(checksum)
+    }
 }
 
 #[aoc(day16, part1)]
-fn problem1(input: &Vec<char>) -> Result<String, ParseError> {
+fn problem1(input: &BitVec) -> Result<String, ParseError> {
     let size = 272;
-    let extended = extend(input, size);
-    let checksum = checksum(&extended, size);
-    Ok(checksum.into_iter().collect::<String>())
+    Dragon::new(input)?.checksum(size)
 }
 
 #[aoc(day16, part2)]
-fn problem2(input: &Vec<char>) -> Result<String, ParseError> {
+fn problem2(input: &BitVec) -> Result<String, ParseError> {
     let size = 35651584;
-    let extended = extend(input, size);
-    let checksum = checksum(&extended, size);
-    Ok(checksum.into_iter().collect::<String>())
+    Dragon::new(input)?.checksum(size)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn extend(input: &BitVec, size: usize) -> Vec<bool> {
+        let mut extended = (0..input.len()).map(|i| input.get(i)).collect::<Vec<_>>();
+
+        while extended.len() < size {
+            let b = extended.iter().rev().map(|v| !v).collect::<Vec<_>>();
+            extended.push(false);
+            extended.extend(b);
+        }
+
+        extended.truncate(size);
+        extended
+    }
+
+    fn checksum(input: &[bool]) -> String {
+        let mut checksum = input.to_vec();
+
+        while checksum.len().is_multiple_of(2) {
+            checksum = checksum.chunks(2).map(|p| p[0] == p[1]).collect::<Vec<_>>();
+        }
+
+        checksum.into_iter().map(|b| if b { '1' } else { '0' }).collect::<String>()
+    }
+
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input("10000")?;
+        assert_eq!("01100", Dragon::new(&input)?.checksum(20)?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn matches_simulation() -> Result<(), ParseError> {
+        for seed in ["1", "0", "10000", "110010110100", "10010000000110000"] {
+            let input = get_input(seed)?;
+            let dragon = Dragon::new(&input)?;
+
+            for size in 1..300 {
+                assert_eq!((seed, size, checksum(&extend(&input, size))), (seed, size, dragon.checksum(size as u64)?));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn huge_disk() -> Result<(), ParseError> {
+        let input = get_input("10010000000110000")?;
+        assert_eq!(1025, Dragon::new(&input)?.checksum(1 << 40 | 1 << 30)?.len());
+        Ok(())
+    }
 }
@@ -1,72 +1,205 @@
+use std::collections::BTreeMap;
 use crate::utils::ParseError;
-use pathfinding::prelude::{dijkstra, dijkstra_all};
+use pathfinding::prelude::bfs;
 
 type C = isize;
-type Coords = (C, C, Vec<char>);
-
-fn door_config(code: &str, path: &Vec<char>) -> [bool; 4] {
-    let input = format!("{}{}", code, path.iter().collect::<String>());
-    let hash = format!("{:x}", md5::compute(input.as_bytes()));
-
-    hash.chars()
-        .take(4)
-        .map(|c| match c {
-            'b'..='f' => true,
-            _ => false,
-        })
-        .collect::<Vec<_>>()
-        .try_into().unwrap()
+type Coords = (C, C);
+type State = (Coords, String);
+
+// Way above the longest path any real passcode produces
+const DEPTH_LIMIT: usize = 100_000;
+
+const DOORS: [(C, C, char); 4] = [(0, -1, 'U'), (0, 1, 'D'), (-1, 0, 'L'), (1, 0, 'R')];
+
+struct Vault {
+    passcode: String,
+    width: C,
+    height: C,
+    start: Coords,
+    exit: Coords,
+    depth_limit: Option<usize>,
 }
 
-fn get_neighbors(code: &str, p: &Coords) -> Vec<Coords> {
-    if p.0 == 3 && p.1 == 3 {
-        return vec![]
+impl Vault {
+    fn new(passcode: &str, width: C, height: C, start: Coords, exit: Coords) -> Self {
+        let passcode = passcode.trim().to_string();
+
+        Self { passcode, width, height, start, exit, depth_limit: None }
+    }
+
+    // The 4x4 vault from the puzzle
+    fn standard(passcode: &str) -> Self {
+        Self::new(passcode, 4, 4, (0, 0), (3, 3))
+    }
+
+    // Paths longer than the limit are not explored
+    fn with_depth_limit(self, depth_limit: usize) -> Self {
+        Self { depth_limit: Some(depth_limit), ..self }
+    }
+
+    fn contains(&self, p: &Coords) -> bool {
+        p.0 >= 0 && p.1 >= 0 && p.0 < self.width && p.1 < self.height
+    }
+
+    fn door_config(&self, path: &str) -> [bool; 4] {
+        let input = format!("{}{}", self.passcode, path);
+        let hash = format!("{:x}", md5::compute(input.as_bytes()));
+        let mut config = [false; 4];
+
+        for (door, c) in config.iter_mut().zip(hash.chars()) {
+            *door = matches!(c, 'b'..='f');
+        }
+
+        config
     }
 
-    let config = door_config(code, &p.2);
+    fn within_limit(&self, path: &str) -> bool {
+        self.depth_limit.map(|limit| path.len() < limit).unwrap_or(true)
+    }
+
+    fn neighbors(&self, state: &State) -> Vec<State> {
+        let (p, path) = state;
+
+        // the vault door closes behind us
+        if *p == self.exit {
+            return vec![];
+        }
+
+        let config = self.door_config(path);
+
+        DOORS.iter()
+            .zip(config)
+            .filter(|(_, open)| *open)
+            .map(|((dx, dy, d), _)| ((p.0 + dx, p.1 + dy), format!("{}{}", path, d)))
+            .filter(|(n, _)| self.contains(n))
+            .collect::<Vec<_>>()
+    }
+
+    fn paths(&self) -> Paths<'_> {
+        let stack = if self.contains(&self.start) { vec![(self.start, String::new())] } else { vec![] };
+
+        Paths { vault: self, stack, pruned: false }
+    }
+
+    fn shortest(&self) -> Result<String, ParseError> {
+        let start = (self.start, String::new());
+
+        let path = bfs(&start, |s| {
+            if self.within_limit(&s.1) { self.neighbors(s) } else { vec![] }
+        }, |s| s.0 == self.exit).ok_or(ParseError::new("Could not find shortest path"))?;
+
+        let (_, last) = path.into_iter().next_back().ok_or(ParseError::new("Path is empty"))?;
+        Ok(last)
+    }
 
-    [(0, -1, 'U'), (0, 1, 'D'), (-1, 0, 'L'), (1, 0, 'R')].into_iter()
-        .enumerate()
-        .map(|(i, c)| {
-            let mut path = p.2.clone();
-            path.push(c.2);
+    // Number of paths to the exit by length. Fails if the depth limit cut off
+    // the search, because the histogram would be incomplete then.
+    fn histogram(&self) -> Result<BTreeMap<usize, usize>, ParseError> {
+        let mut paths = self.paths();
+        let mut histogram = BTreeMap::new();
 
-            (c.0 + p.0, c.1 + p.1, path, config[i])
-        })
-        .filter(|c| c.0 >= 0 && c.1 >= 0 && c.0 <= 3 && c.1 <= 3 && c.3)
-        .map(|c| {
-            (c.0, c.1, c.2)
-        })
-        .collect::<Vec<_>>()
+        for path in paths.by_ref() {
+            *histogram.entry(path.len()).or_insert(0) += 1;
+        }
+
+        if paths.pruned {
+            Err(ParseError::new(&format!("Reached depth limit of {:?}", self.depth_limit)))?;
+        }
+
+        Ok(histogram)
+    }
+
+    fn longest(&self) -> Result<usize, ParseError> {
+        let histogram = self.histogram()?;
+        let (longest, _) = histogram.iter().next_back().ok_or(ParseError::new("Could not find path with max length"))?;
+
+        Ok(*longest)
+    }
+
+    #[allow(dead_code)]
+    fn count(&self) -> Result<usize, ParseError> {
+        Ok(self.histogram()?.values().sum())
+    }
+}
+
+// Depth first enumeration of all paths from the start to the exit
+struct Paths<'a> {
+    vault: &'a Vault,
+    stack: Vec<State>,
+    pruned: bool,
 }
 
-fn get_weighted_neighbors(code: &str, p: &Coords) -> Vec<(Coords, usize)> {
-    get_neighbors(code, p).into_iter()
-        .map(|c| (c, 1))
-        .collect::<Vec<_>>()
+impl Iterator for Paths<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(state) = self.stack.pop() {
+            if state.0 == self.vault.exit {
+                return Some(state.1);
+            }
+
+            if !self.vault.within_limit(&state.1) {
+                self.pruned = true;
+                continue;
+            }
+
+            self.stack.append(&mut self.vault.neighbors(&state));
+        }
+
+        None
+    }
 }
 
 #[aoc(day17, part1)]
 fn problem1(input: &str) -> Result<String, ParseError> {
-    let start = (0, 0, vec![]);
-
-    let shortest = dijkstra(&start, |p| get_weighted_neighbors(input, p), |p| p.0 == 3 && p.1 == 3)
-        .ok_or(ParseError::new("Could not find shortest path"))?;
-    let path = shortest.0.into_iter().rev().next().ok_or(ParseError::new("Path is empty"))?;
-    Ok(path.2.into_iter().collect::<String>())
+    Vault::standard(input).with_depth_limit(DEPTH_LIMIT).shortest()
 }
 
 #[aoc(day17, part2)]
 fn problem2(input: &str) -> Result<usize, ParseError> {
-    let start = (0, 0, vec![]);
+    Vault::standard(input).with_depth_limit(DEPTH_LIMIT).longest()
+}
 
-    let all = dijkstra_all(&start, |p| get_weighted_neighbors(input, p));
-    let mut lengths = all.into_iter()
-        .filter(|(k, _)| k.0 == 3 && k.1 == 3)
-        .map(|(_, v)| v.1)
-        .collect::<Vec<_>>();
-    lengths.sort();
-    let max = lengths.into_iter().rev().next().ok_or(ParseError::new("Could not find path with max length"))?;
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    Ok(max)
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let vault = Vault::standard(include_str!("example-1"));
+        assert_eq!("DDRRRD", vault.shortest()?);
+        assert_eq!(370, vault.longest()?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn example_2() -> Result<(), ParseError> {
+        let vault = Vault::standard(include_str!("example-2"));
+        assert_eq!("DDUDRLRRUDRD", vault.shortest()?);
+        assert_eq!(492, vault.longest()?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn example_3() -> Result<(), ParseError> {
+        let vault = Vault::standard(include_str!("example-3"));
+        assert_eq!("DRURDRUDDLLDLUURRDULRLDUUDDDRR", vault.shortest()?);
+        assert_eq!(830, vault.longest()?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn no_exit() {
+        assert!(Vault::standard("hijkl").shortest().is_err());
+        assert_eq!(0, Vault::standard("hijkl").count().unwrap_or(1));
+    }
+
+    #[test]
+    pub fn configured() -> Result<(), ParseError> {
+        let vault = Vault::new("ihgpwlah", 2, 3, (1, 2), (0, 0));
+        let count = vault.count()?;
+        assert_eq!(count, vault.paths().count());
+        assert!(Vault::standard("ihgpwlah").with_depth_limit(20).longest().is_err());
+        Ok(())
+    }
 }
@@ -1,63 +1,188 @@
 use crate::utils::ParseError;
+use crate::utils::bitvec::BitVec;
 
-#[aoc_generator(day18)]
-fn get_input(input: &str) -> Result<Vec<char>, ParseError> {
-    Ok(input.chars().collect::<Vec<_>>())
+// All four trap rules as written boil down to: a tile is a trap if exactly one
+// of its left and right neighbors is a trap. That's Wolfram's rule 90.
+const TRAP_RULE: u8 = 90;
+
+// What the cells beyond either end of a row look like
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Boundary {
+    // always zero, the puzzle's safe walls
+    Safe,
+    // the row is a ring
+    Wrap,
+    // the edge cells see themselves
+    Mirror,
 }
 
-fn is_trap(window: &[char]) -> bool {
-    match window {
-        // rules as written, in order
-        ['^', '^', '.'] => true,
-        ['.', '^', '^'] => true,
-        ['^', '.', '.'] => true,
-        ['.', '.', '^'] => true,
-        _ => false,
-    }
+// Elementary cellular automaton, each row is derived from the row above
+struct Automaton {
+    rule: u8,
+    boundary: Boundary,
 }
 
-fn next_row(row: &Vec<char>) -> Vec<char> {
-    let mut extended = vec!['.'];
-    extended.append(&mut row.clone());
-    extended.push('.');
+impl Automaton {
+    fn new(rule: u8, boundary: Boundary) -> Self {
+        Self { rule, boundary }
+    }
+
+    // Applies the rule to 64 cells at once. Every set bit in the rule number
+    // selects one neighborhood pattern that turns the cell on.
+    fn apply(&self, left: u64, center: u64, right: u64) -> u64 {
+        let mut result = 0;
 
-    let mut next = vec![];
-    for window in extended.windows(3) {
-        next.push(if is_trap(window) {
-            '^'
-        } else {
-            '.'
-        });
+        for pattern in 0..8 {
+            if self.rule >> pattern & 1 == 0 {
+                continue;
+            }
+
+            let l = if pattern & 4 != 0 { left } else { !left };
+            let c = if pattern & 2 != 0 { center } else { !center };
+            let r = if pattern & 1 != 0 { right } else { !right };
+            result |= l & c & r;
+        }
+
+        result
     }
 
-    next
-}
+    fn step(&self, row: &BitVec) -> BitVec {
+        if row.is_empty() {
+            return row.clone();
+        }
 
-fn count_safe(map: &Vec<Vec<char>>) -> usize {
-    map.iter()
-        .map(|v| v.iter().filter(|c| **c == '.').count())
-        .sum()
-}
+        if self.rule == TRAP_RULE && self.boundary == Boundary::Safe {
+            return row.xor_neighbours();
+        }
 
-fn extend_and_count(start: &Vec<char>, target_size: usize) -> usize {
-    let mut map = vec![start.clone()];
-    for i in 0..target_size-1 {
-        let next = next_row(&map[i]);
-        map.push(next);
+        let first = row.get(0);
+        let last = row.get(row.len() - 1);
+        let (before, after) = match self.boundary {
+            Boundary::Safe => (false, false),
+            Boundary::Wrap => (last, first),
+            Boundary::Mirror => (first, last),
+        };
+
+        let left = row.shifted_up(before);
+        let right = row.shifted_down(after);
+        BitVec::combine(&left, row, &right, |l, c, r| self.apply(l, c, r))
     }
 
-    count_safe(&map)
+    // Total number of ones in the first `rows` rows, starting with `start`.
+    // Only two rows are kept around. Brent's algorithm runs alongside to find
+    // a cycle, once one shows up all remaining full periods are skipped.
+    fn count_ones(&self, start: &BitVec, rows: usize) -> usize {
+        let mut row = start.clone();
+        let mut index = 0;
+        let mut total = 0;
+
+        let mut tortoise = row.clone();
+        let mut tortoise_total = 0;
+        let mut power = 1;
+        let mut lambda = 0;
+        let mut searching = true;
+
+        while index < rows {
+            total += row.count_ones();
+            row = self.step(&row);
+            index += 1;
+
+            if !searching {
+                continue;
+            }
+
+            lambda += 1;
+            if row == tortoise {
+                // rows repeat every lambda steps from here on
+                let period_total = total - tortoise_total;
+                let periods = (rows - index) / lambda;
+                total += periods * period_total;
+                index += periods * lambda;
+                searching = false;
+            } else if lambda == power {
+                tortoise = row.clone();
+                tortoise_total = total;
+                power *= 2;
+                lambda = 0;
+            }
+        }
+
+        total
+    }
+}
+
+// Traps are stored as ones.
+#[aoc_generator(day18)]
+fn get_input(input: &str) -> Result<BitVec, ParseError> {
+    BitVec::parse(input.trim(), '.', '^')
+}
+
+fn extend_and_count(start: &BitVec, target_size: usize) -> usize {
+    let traps = Automaton::new(TRAP_RULE, Boundary::Safe).count_ones(start, target_size);
+    start.len() * target_size - traps
 }
 
 #[aoc(day18, part1)]
-fn problem1(input: &Vec<char>) -> Result<usize, ParseError> {
+fn problem1(input: &BitVec) -> Result<usize, ParseError> {
     // account for the reduced example size
     let target_size = if input.len() == 10 { 10 } else { 40 };
     Ok(extend_and_count(input, target_size))
 }
 
 #[aoc(day18, part2)]
-fn problem2(input: &Vec<char>) -> Result<usize, ParseError> {
+fn problem2(input: &BitVec) -> Result<usize, ParseError> {
     let target_size = 400_000;
     Ok(extend_and_count(input, target_size))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example"))?;
+        assert_eq!(38, problem1(&input)?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn cycles_match_simulation() -> Result<(), ParseError> {
+        let start = get_input("..^^.^..^^^")?;
+
+        for rule in [30, 90, 110, 150, 184] {
+            for boundary in [Boundary::Safe, Boundary::Wrap, Boundary::Mirror] {
+                let automaton = Automaton::new(rule, boundary);
+                let mut row = start.clone();
+                let mut simulated = 0;
+
+                for rows in 0..300 {
+                    assert_eq!((rule, boundary, rows, simulated), (rule, boundary, rows, automaton.count_ones(&start, rows)));
+                    simulated += row.count_ones();
+                    row = automaton.step(&row);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn rule_90_shortcut() -> Result<(), ParseError> {
+        let row = get_input(".^^.^.^^^^..^...^^^.^^^^.^.^^^^^^^.^^..^^^^^^.^^.^^^^^^.^.^.^.^^^^.^^.^^^^^.^")?;
+        let generic = Automaton::new(TRAP_RULE, Boundary::Safe);
+        let expected = BitVec::combine(&row.shifted_up(false), &row, &row.shifted_down(false), |l, c, r| generic.apply(l, c, r));
+
+        assert_eq!(expected, generic.step(&row));
+        Ok(())
+    }
+
+    #[test]
+    pub fn huge_row_count() -> Result<(), ParseError> {
+        let start = get_input(".^^.^.^^^^")?;
+        let automaton = Automaton::new(30, Boundary::Wrap);
+        assert!(automaton.count_ones(&start, 1 << 50) > 0);
+        Ok(())
+    }
+}
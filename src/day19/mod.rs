@@ -1,48 +1,15 @@
 use std::collections::VecDeque;
-use std::mem::size_of;
 use crate::utils::ParseError;
 
 #[aoc_generator(day19)]
 fn get_input(input: &str) -> Result<usize, ParseError> {
-    Ok(input.parse::<usize>()?)
-}
-
-#[allow(dead_code)]
-fn find_next(elves: &Vec<u8>, start: usize) -> Option<usize> {
-    let len = elves.len();
-    elves.iter()
-        .enumerate()
-        .cycle()
-        .skip(start + 1)
-        .take(len-1).filter(|(_, v)| **v != 0)
-        .map(|(i, _)| i)
-        .next()
-}
-
-// Simulation. Works for smaller n but not for 3M+.
-#[allow(dead_code)]
-fn find_survivor(n: usize) -> Result<usize, ParseError> {
-    let mut pos = 0;
-    let mut elves = vec![1; n];
+    let n = input.trim().parse::<usize>()?;
 
-    while let Some(e) = find_next(&elves, pos) {
-        elves[pos] += elves[e];
-        elves[e] = 0;
-
-        if let Some(p_next) = find_next(&elves, e) {
-            pos = p_next;
-        } else {
-            break;
-        }
+    if n == 0 {
+        Err(ParseError::new("There must be at least one elf"))?;
     }
 
-    let result = elves.iter().enumerate()
-        .filter(|(_, v)| **v != 0)
-        .map(|(i, _)| i)
-        .next()
-        .ok_or(ParseError::new("No elf wins"))?;
-
-    Ok(result + 1)
+    Ok(n)
 }
 
 // Today's puzzle is also known as the Josephus Problem.
@@ -51,32 +18,83 @@ fn find_survivor(n: usize) -> Result<usize, ParseError> {
 // Our configuration for part 1 is
 //    n = number of elves
 //    k = 2 (every other elf gets their presents stolen)
-#[aoc(day19, part1)]
-fn problem1(input: &usize) -> Result<usize, ParseError> {
-    let m = size_of::<usize>() * 8 - input.leading_zeros() as usize - 1;
-    let l = input - (1 << m);
+//
+// Returns the winning elf for any k >= 1 in O(k log n). Every round of the
+// recurrence removes n / k elves at once, the survivor's position in the
+// smaller circle is then mapped back onto the larger one.
+fn josephus(n: usize, k: usize) -> usize {
+    if n <= 1 || k == 1 {
+        return n;
+    }
+
+    let mut sizes = vec![];
+    let mut m = n;
+    while m >= k {
+        sizes.push(m);
+        m -= m / k;
+    }
+
+    // few enough elves left to go one at a time
+    let mut winner = 0;
+    for i in 2..=m {
+        winner = (winner + k) % i;
+    }
 
-    Ok(2*l + 1)
+    while let Some(m) = sizes.pop() {
+        let shift = m % k;
+        winner = if winner < shift {
+            winner + m - shift
+        } else {
+            let w = winner - shift;
+            w + w / (k - 1)
+        };
+    }
+
+    winner + 1
 }
 
-// Simulating implementation that is far too slow for the actual input.
-// But it helps to find patterns in smaller n.
-// If we look at the winners for n up to 1000 we find that the winner for
-// n elves where n = 3^k + 1, k in N is elf #1.
-// The winner for n = 3^k + i is either i for 3^k < n <= (3^(k+1) + 3^k) / 2
-// and 3^k + 2*(i - 3^k) for (3^(k+1) + 3^k) / 2 < n <= 3^(k+1)
+// Elves in the order they lose their presents when every k-th elf is
+// robbed, the winner comes last.
 #[allow(dead_code)]
-fn stupid(n: usize) -> usize {
+fn elimination_order(n: usize, k: usize) -> Vec<usize> {
     let mut elves = (1..=n).collect::<VecDeque<_>>();
+    let mut order = vec![];
+
+    while !elves.is_empty() {
+        let len = elves.len();
+        elves.rotate_left((k + len - 1) % len);
+        order.extend(elves.pop_front());
+    }
+
+    order
+}
+
+#[aoc(day19, part1)]
+fn problem1(input: &usize) -> Result<usize, ParseError> {
+    Ok(josephus(*input, 2))
+}
 
-    while elves.len() > 1 {
-        let rot = elves.len() / 2;
-        elves.rotate_left(rot);
-        elves.pop_front();
-        elves.rotate_right(rot - 1);
+// Elves in the order they lose their presents when the elf across the
+// circle is robbed, the winner comes last. The circle is split into two
+// halves so the elf across is always at the seam, which makes this O(n).
+#[allow(dead_code)]
+fn across_order(n: usize) -> Vec<usize> {
+    let mut left = (1..=n / 2).collect::<VecDeque<_>>();
+    let mut right = (n / 2 + 1..=n).collect::<VecDeque<_>>();
+    let mut order = vec![];
+
+    while left.len() + right.len() > 1 {
+        let victim = if left.len() > right.len() { left.pop_back() } else { right.pop_front() };
+        order.extend(victim);
+
+        // the next elf takes the turn
+        right.extend(left.pop_front());
+        left.extend(right.pop_front());
     }
 
-    elves[0]
+    order.extend(left);
+    order.extend(right);
+    order
 }
 
 fn find_powers(n: usize) -> (usize, usize) {
@@ -91,12 +109,16 @@ fn find_powers(n: usize) -> (usize, usize) {
     (low, high)
 }
 
-// fast solution, only works for n > 1
+// Fast solution for the elf across the circle.
+// Looking at the winners for n up to 1000 we find that the winner for
+// n elves where n = 3^k + 1, k in N is elf #1.
+// The winner for n = 3^k + i is either i for 3^k < n <= (3^(k+1) + 3^k) / 2
+// and 3^k + 2*(i - 3^k) for (3^(k+1) + 3^k) / 2 < n <= 3^(k+1)
 fn find_winner(n: usize) -> usize {
     let (low, high)= find_powers(n);
     let mean = (low + high) / 2;
 
-    if n == low {
+    if n <= 1 || n == low {
         n
     } else if n <= mean {
         n - low
@@ -126,11 +148,33 @@ mod test {
 
     #[test]
     fn find_winner__1_to_1000__correct() {
-        for i in 2..=1000 {
-            let simple = stupid(i);
+        for i in 1..=1000 {
+            let simple = across_order(i).last().cloned();
             let fast = find_winner(i);
 
-            assert_eq!((i, simple), (i, fast));
+            assert_eq!((i, simple), (i, Some(fast)));
         }
     }
+
+    #[test]
+    fn across_order__5__matches_example() {
+        assert_eq!(vec![3, 5, 1, 4, 2], across_order(5));
+    }
+
+    #[test]
+    fn josephus__matches_simulation() {
+        for k in 1..=7 {
+            for n in 1..=300 {
+                let simple = elimination_order(n, k).last().cloned();
+                let fast = josephus(n, k);
+
+                assert_eq!((n, k, simple), (n, k, Some(fast)));
+            }
+        }
+    }
+
+    #[test]
+    fn elimination_order__5__matches_example() {
+        assert_eq!(vec![2, 4, 1, 5, 3], elimination_order(5, 2));
+    }
 }
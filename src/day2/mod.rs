@@ -1,108 +1,266 @@
-use std::cmp::min;
-use std::cmp::max;
+use std::collections::HashMap;
 use crate::utils::ParseError;
+use pathfinding::prelude::astar_bag_collect;
 
 type C = isize;
 type Coords = (C, C);
 
-#[aoc_generator(day2)]
-fn get_input(input: &str) -> Result<Vec<Vec<char>>, ParseError> {
-    Ok(input
-        .lines()
-        .map(|l| l.chars().collect::<Vec<_>>())
-        .collect::<Vec<_>>())
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Move {
+    Up,
+    Down,
+    Left,
+    Right,
 }
 
-fn keypad() -> Vec<Vec<u8>> {
-    "123\n456\n789"
-        .lines()
-        .map(|l| l.chars().map(|c| c.to_digit(10).unwrap() as u8).collect::<Vec<_>>())
-        .collect::<Vec<_>>()
-}
+impl TryFrom<char> for Move {
+    type Error = ParseError;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        use Move::*;
 
-fn trace(start: Coords, dir: &Vec<char>) -> Coords {
-    let mut p = start;
-
-    for d in dir {
-        let next = match d {
-            'U' => {
-                (p.0, p.1 - 1)
-            },
-            'L' => {
-                (p.0 - 1, p.1)
-            },
-            'D' => {
-                (p.0, p.1 + 1)
-            },
-            'R' => {
-                (p.0 + 1, p.1)
-            },
-            _ => panic!("Impossible!"),
-        };
-        p.0 = min(2, max(next.0, 0));
-        p.1 = min(2, max(next.1, 0));
-    }
-
-    p
+        Ok(match c {
+            'U' => Up,
+            'D' => Down,
+            'L' => Left,
+            'R' => Right,
+            _ => Err(ParseError::new(&format!("Invalid move {}", c)))?,
+        })
+    }
 }
 
-#[aoc(day2, part1)]
-fn problem1(input: &Vec<Vec<char>>) -> Result<String, ParseError> {
-    let kp = keypad();
-    let mut pos = (1, 1);
-    let mut code = vec![];
-    for n in input {
-        pos = trace(pos, n);
-        code.push(kp[pos.1 as usize][pos.0 as usize]);
-    }
-    Ok(code.iter().map(|v| char::from_digit(*v as u32, 10).unwrap()).collect::<String>())
+impl Move {
+    fn dir(&self) -> Coords {
+        use Move::*;
+
+        match self {
+            Up => (0, -1),
+            Down => (0, 1),
+            Left => (-1, 0),
+            Right => (1, 0),
+        }
+    }
+
+    #[allow(dead_code)]
+    fn to_char(self) -> char {
+        use Move::*;
+
+        match self {
+            Up => 'U',
+            Down => 'D',
+            Left => 'L',
+            Right => 'R',
+        }
+    }
 }
 
-fn keypad2() -> Vec<Vec<char>> {
-    "  1  \n 234 \n56789\n ABC \n  D  "
+#[allow(dead_code)]
+const MOVES: [Move; 4] = [Move::Up, Move::Down, Move::Left, Move::Right];
+
+#[aoc_generator(day2)]
+fn get_input(input: &str) -> Result<Vec<Vec<Move>>, ParseError> {
+    input
         .lines()
-        .map(|l| l.chars().map(|c| c).collect::<Vec<_>>())
-        .collect::<Vec<_>>()
+        .enumerate()
+        .map(|(i, l)| l.trim().chars()
+            .map(Move::try_from)
+            .collect::<Result<Vec<_>, ParseError>>()
+            .map_err(|e| ParseError::new(&format!("{} in line {}", e, i + 1))))
+        .collect::<Result<Vec<_>, ParseError>>()
+}
+
+// A keypad drawn as ASCII art. Every character except space is a key, spaces
+// and everything beyond the end of a line are holes nobody can move onto.
+struct Keypad {
+    keys: HashMap<Coords, char>,
+    width: C,
+    height: C,
+    start: Coords,
+    wrap: bool,
+}
+
+impl Keypad {
+    fn parse(layout: &str, start: char) -> Result<Self, ParseError> {
+        let mut keys = HashMap::new();
+        let mut width = 0;
+        let mut height = 0;
+
+        for (y, line) in layout.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+
+                if keys.values().any(|k| *k == c) {
+                    Err(ParseError::new(&format!("Key {} appears more than once", c)))?;
+                }
+                keys.insert((x as C, y as C), c);
+                width = width.max(x as C + 1);
+            }
+            height = y as C + 1;
+        }
+
+        let mut keypad = Self { keys, width, height, start: (0, 0), wrap: false };
+        keypad.start = keypad.position(start)?;
+
+        Ok(keypad)
+    }
+
+    fn position(&self, key: char) -> Result<Coords, ParseError> {
+        self.keys.iter()
+            .find(|(_, k)| **k == key)
+            .map(|(p, _)| *p)
+            .ok_or(ParseError::new(&format!("Key {} is not on the keypad", key)))
+    }
+
+    // Moving off an edge continues on the opposite edge
+    #[allow(dead_code)]
+    fn with_wrap(self) -> Self {
+        Self { wrap: true, ..self }
+    }
+
+    fn key(&self, p: Coords) -> Option<char> {
+        self.keys.get(&p).cloned()
+    }
+
+    // Without wrapping a move onto a hole is ignored. With wrapping the move
+    // skips over holes until it reaches the next key in that direction.
+    fn step(&self, p: Coords, m: Move) -> Coords {
+        let (dx, dy) = m.dir();
+
+        if !self.wrap {
+            let next = (p.0 + dx, p.1 + dy);
+            return if self.keys.contains_key(&next) { next } else { p };
+        }
+
+        let mut next = p;
+        loop {
+            next = ((next.0 + dx).rem_euclid(self.width), (next.1 + dy).rem_euclid(self.height));
+            if self.keys.contains_key(&next) {
+                return next;
+            }
+        }
+    }
+
+    fn trace(&self, start: Coords, moves: &[Move]) -> Coords {
+        moves.iter().fold(start, |p, m| self.step(p, *m))
+    }
+
+    fn code(&self, lines: &[Vec<Move>]) -> Result<String, ParseError> {
+        let mut pos = self.start;
+        let mut code = String::new();
+
+        for line in lines {
+            pos = self.trace(pos, line);
+            code.push(self.key(pos).ok_or(ParseError::new("Left the keypad"))?);
+        }
+
+        Ok(code)
+    }
 }
 
-fn trace2(start: Coords, dir: &Vec<char>, kp: &Vec<Vec<char>>) -> Coords {
-    let mut p = start;
-
-    for d in dir {
-        let mut next = match d {
-            'U' => {
-                (p.0, p.1 - 1)
-            },
-            'L' => {
-                (p.0 - 1, p.1)
-            },
-            'D' => {
-                (p.0, p.1 + 1)
-            },
-            'R' => {
-                (p.0 + 1, p.1)
-            },
-            _ => panic!("Impossible!"),
-        };
-
-        next.0 = min(4, max(next.0, 0));
-        next.1 = min(4, max(next.1, 0));
-        if kp[next.1 as usize][next.0 as usize] != ' ' {
-            p = next;
+// Reverse solver to find instructions that type a given code
+#[allow(dead_code)]
+impl Keypad {
+    // Every shortest line of moves from one key to another, sorted. A line
+    // that walks into a hole is never shortest, so holes need no extra care.
+    fn shortest_lines(&self, from: Coords, to: Coords) -> Result<Vec<String>, ParseError> {
+        // the last move is part of the node, so that two moves leading to
+        // the same key on a wrapping keypad count as different lines
+        let start = (from, None);
+        let (paths, _) = astar_bag_collect(
+            &start,
+            |(p, _)| MOVES.iter()
+                .map(|m| ((self.step(*p, *m), Some(*m)), 1))
+                .filter(|((n, _), _)| n != p)
+                .collect::<Vec<_>>(),
+            |_| 0,
+            |(p, _)| *p == to,
+        ).ok_or(ParseError::new(&format!("Cannot reach {:?} from {:?}", to, from)))?;
+
+        let mut lines = paths.iter()
+            .map(|path| path.iter().filter_map(|(_, m)| m.map(Move::to_char)).collect::<String>())
+            .collect::<Vec<_>>();
+        lines.sort();
+        lines.dedup();
+
+        Ok(lines)
+    }
+
+    // For each key of the code all equally short lines that end on it when
+    // starting from where the previous line ended
+    fn solve(&self, code: &str) -> Result<Vec<Vec<String>>, ParseError> {
+        let mut pos = self.start;
+        let mut lines = vec![];
+
+        for key in code.chars() {
+            let next = self.position(key)?;
+            lines.push(self.shortest_lines(pos, next)?);
+            pos = next;
         }
+
+        Ok(lines)
     }
+}
+
+const KEYPAD: &str = "123\n456\n789";
+const KEYPAD2: &str = "  1  \n 234 \n56789\n ABC \n  D  ";
 
-    p
+#[aoc(day2, part1)]
+fn problem1(input: &[Vec<Move>]) -> Result<String, ParseError> {
+    Keypad::parse(KEYPAD, '5')?.code(input)
 }
 
 #[aoc(day2, part2)]
-fn problem2(input: &Vec<Vec<char>>) -> Result<String, ParseError> {
-    let kp = keypad2();
-    let mut pos = (0, 2);
-    let mut code = vec![];
-    for n in input {
-        pos = trace2(pos, n, &kp);
-        code.push(kp[pos.1 as usize][pos.0 as usize]);
-    }
-    Ok(code.iter().collect::<String>())
+fn problem2(input: &[Vec<Move>]) -> Result<String, ParseError> {
+    Keypad::parse(KEYPAD2, '5')?.code(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example"))?;
+
+        assert_eq!("1985", problem1(&input)?);
+        assert_eq!("5DB3", problem2(&input)?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn invalid_input() {
+        assert!(get_input("UDLR\nUDXR").is_err());
+        assert!(Keypad::parse(KEYPAD, '0').is_err());
+        assert!(Keypad::parse("121", '1').is_err());
+    }
+
+    #[test]
+    pub fn wrap() -> Result<(), ParseError> {
+        let input = get_input("U\nLL\nR\nDD")?;
+
+        assert_eq!("2317", Keypad::parse(KEYPAD, '5')?.with_wrap().code(&input)?);
+        // left of 5 is a hole until the row wraps around to 9
+        assert_eq!("9", Keypad::parse(KEYPAD2, '5')?.with_wrap().code(&get_input("L")?)?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn solve() -> Result<(), ParseError> {
+        let keypad = Keypad::parse(KEYPAD2, '5')?;
+        let lines = keypad.solve("5DB3")?;
+
+        assert_eq!(vec![vec![""], vec!["RDRD", "RRDD"], vec!["U"], vec!["UU"]], lines);
+
+        // every alternative types the code again
+        for first in &lines[1] {
+            let input = get_input(&format!("\n{}\nU\nUU", first))?;
+            assert_eq!("5DB3", keypad.code(&input)?);
+        }
+
+        assert_eq!(vec![vec!["L", "R"]], Keypad::parse("12", '1')?.with_wrap().solve("2")?);
+        assert!(keypad.solve("5X").is_err());
+        Ok(())
+    }
 }
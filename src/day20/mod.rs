@@ -1,78 +1,140 @@
-use std::cmp::max;
-use std::ops::Range;
-use std::num::ParseIntError;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
 use crate::utils::ParseError;
+use crate::utils::rangeset::{Integer, RangeSet};
 
-#[aoc_generator(day20)]
-fn get_input(input: &str) -> Result<Vec<Range<usize>>, ParseError> {
-    input.lines()
-        .map(|l| -> Result<Range<usize>, ParseError> {
-            let range = l.split("-")
-                .map(|s| s.parse::<usize>())
-                .collect::<Result<Vec<_>, ParseIntError>>()?;
-
-            if range.len() != 2 {
-                Err(ParseError::new("Invalid range"))?;
-            }
-
-            Ok(Range { start: range[0], end: range[1] })
-        })
-        .collect::<Result<Vec<_>, ParseError>>()
+// Addresses a blocklist can be made of. Each line is either a range `a-b`
+// with both ends included or a CIDR block like `10.0.0.0/8`.
+trait Address: Integer + FromStr {
+    const BITS: u32;
+
+    fn from_u128(v: u128) -> Self;
+    fn to_u128(self) -> u128;
+    fn from_ip(s: &str) -> Option<Self>;
 }
 
-#[aoc(day20, part1)]
-fn problem1(input: &Vec<Range<usize>>) -> Result<usize, ParseError> {
-    let mut ranges = input.clone();
-    ranges.sort_by(|a, b| a.start.cmp(&b.start));
-
-    let mut lowest = 0;
-    for r in &ranges {
-        if r.start <= lowest && lowest < r.end {
-            lowest = r.end + 1;
-        }
+impl Address for u32 {
+    const BITS: u32 = u32::BITS;
+
+    fn from_u128(v: u128) -> Self { v as u32 }
+    fn to_u128(self) -> u128 { self as u128 }
+    fn from_ip(s: &str) -> Option<Self> {
+        s.parse::<Ipv4Addr>().ok().map(u32::from)
     }
+}
+
+impl Address for u64 {
+    const BITS: u32 = u64::BITS;
 
-    Ok(lowest)
+    fn from_u128(v: u128) -> Self { v as u64 }
+    fn to_u128(self) -> u128 { self as u128 }
+    fn from_ip(_s: &str) -> Option<Self> {
+        None
+    }
 }
 
-fn merge(ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
-    let mut merged = vec![];
-
-    let mut start = ranges[0].start;
-    let mut end = ranges[0].end;
-    let mut open = true;
-    for r in ranges.iter().skip(1) {
-        if end < r.start {
-            merged.push(Range { start, end });
-            start = r.start;
-            end = r.end;
-            open = false;
-            continue;
+impl Address for u128 {
+    const BITS: u32 = u128::BITS;
+
+    fn from_u128(v: u128) -> Self { v }
+    fn to_u128(self) -> u128 { self }
+    fn from_ip(s: &str) -> Option<Self> {
+        s.parse::<Ipv6Addr>().ok().map(u128::from)
+    }
+}
+
+fn parse_address<T: Address>(s: &str) -> Result<T, ParseError> {
+    let s = s.trim();
+
+    s.parse::<T>().ok()
+        .or_else(|| T::from_ip(s))
+        .ok_or(ParseError::new(&format!("Invalid address {}", s)))
+}
+
+fn parse_block<T: Address>(s: &str) -> Result<(T, T), ParseError> {
+    if let Some((address, prefix)) = s.split_once('/') {
+        let address = parse_address::<T>(address)?.to_u128();
+        let prefix = prefix.trim().parse::<u32>()?;
+
+        if prefix > T::BITS {
+            Err(ParseError::new(&format!("Prefix of {} is too long", s)))?;
         }
 
-        open = true;
-        end = max(end, r.end);
+        let host_bits = T::BITS - prefix;
+        let host_mask = if host_bits == 0 { 0 } else { u128::MAX >> (u128::BITS - host_bits) };
+
+        return Ok((T::from_u128(address & !host_mask), T::from_u128(address | host_mask)));
     }
 
-    if open {
-        merged.push(Range { start, end });
+    let (start, end) = s.split_once('-').ok_or(ParseError::new(&format!("Invalid range {}", s)))?;
+    let start = parse_address::<T>(start)?;
+    let end = parse_address::<T>(end)?;
+
+    if end < start {
+        Err(ParseError::new(&format!("Range {} ends before it starts", s)))?;
     }
-    merged
+
+    Ok((start, end))
+}
+
+fn parse_blocklist<T: Address>(input: &str) -> Result<RangeSet<T>, ParseError> {
+    input.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(parse_block::<T>)
+        .collect::<Result<RangeSet<_>, ParseError>>()
+}
+
+#[aoc_generator(day20)]
+fn get_input(input: &str) -> Result<RangeSet<u32>, ParseError> {
+    parse_blocklist(input)
+}
+
+#[aoc(day20, part1)]
+fn problem1(input: &RangeSet<u32>) -> Result<u32, ParseError> {
+    input.complement(u32::MIN, u32::MAX)
+        .values()
+        .next()
+        .ok_or(ParseError::new("All addresses are blocked"))
 }
 
 #[aoc(day20, part2)]
-fn problem2(input: &Vec<Range<usize>>) -> Result<usize, ParseError> {
-    let mut ranges = input.clone();
-    ranges.sort_by(|a, b| a.start.cmp(&b.start));
-    ranges = merge(ranges);
-
-    let mut allowed = ranges[0].start;
-    for r in ranges.windows(2) {
-        allowed += r[1].start - r[0].end - 1;
+fn problem2(input: &RangeSet<u32>) -> Result<usize, ParseError> {
+    input.complement(u32::MIN, u32::MAX)
+        .len()
+        .and_then(|len| usize::try_from(len).ok())
+        .ok_or(ParseError::new("Too many allowed addresses"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let blocked = parse_blocklist::<u32>(include_str!("example"))?;
+        let allowed = blocked.complement(0, 9);
+
+        assert_eq!(vec![3, 9], allowed.values().collect::<Vec<_>>());
+        Ok(())
     }
-    let max = u32::MAX as usize;
-    let last = ranges[ranges.len() - 1].end;
-    allowed += max - last;
 
-    Ok(allowed)
+    #[test]
+    pub fn cidr() -> Result<(), ParseError> {
+        assert_eq!((0x0a000000, 0x0affffff), parse_block::<u32>("10.0.0.0/8")?);
+        assert_eq!((0, u32::MAX), parse_block::<u32>("1.2.3.4/0")?);
+        assert_eq!((7, 7), parse_block::<u32>("0.0.0.7/32")?);
+        assert_eq!((1 << 64, (1 << 64) | 0xffff), parse_block::<u128>("::1:0:0:0:0/112")?);
+        assert!(parse_block::<u32>("10.0.0.0/33").is_err());
+        assert!(parse_block::<u32>("5-2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn ipv6() -> Result<(), ParseError> {
+        let blocked = parse_blocklist::<u128>("::/1\n8000::-ffff:ffff:ffff:ffff:ffff:ffff:ffff:fffe\n")?;
+        let allowed = blocked.complement(0, u128::MAX);
+
+        assert_eq!(vec![u128::MAX], allowed.values().collect::<Vec<_>>());
+        Ok(())
+    }
 }
@@ -1,60 +1,239 @@
-use regex::Regex;
+use std::collections::BTreeMap;
+use std::fmt;
 use crate::utils::ParseError;
+use crate::utils::table::{group, parse_table, Order};
 
 type Triangle = [usize; 3];
 
-fn parse_triangle(s: &str) -> Result<Triangle, ParseError> {
-    lazy_static!{
-        static ref RE: Regex = Regex::new(r"^\s*(?P<a>\d*?)\s*(?P<b>\d*?)\s*(?P<c>\d*)\w*$").unwrap();
+#[aoc_generator(day3)]
+fn get_input(input: &str) -> Result<Vec<Triangle>, ParseError> {
+    group(&parse_table::<usize>(input, 3)?, Order::RowMajor)
+}
+
+fn is_valid(triangle: &Triangle) -> bool {
+    classify(triangle) > Class::Degenerate
+}
+
+// With integer sides a right triangle is always scalene, the hypotenuse of
+// an isosceles one would be a * sqrt(2). So right-angled can be a class of
+// its own instead of a flag on top of the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Class {
+    // not a proper triangle, the longest side is at least as long as the
+    // other two together
+    Degenerate,
+    Scalene,
+    Right,
+    Isosceles,
+    Equilateral,
+}
+
+const CLASSES: [Class; 5] = [Class::Degenerate, Class::Scalene, Class::Right, Class::Isosceles, Class::Equilateral];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Area {
+    // area in quarters, Heron's formula gives a multiple of 1/4 whenever
+    // the area is rational at all
+    Exact(u128),
+    Approximate(f64),
+}
+
+impl fmt::Display for Area {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Area::Exact(quarters) if quarters % 4 == 0 => write!(f, "{}", quarters / 4),
+            Area::Exact(quarters) => write!(f, "{}", *quarters as f64 / 4.0),
+            Area::Approximate(area) => write!(f, "~{:.3}", area),
+        }
     }
+}
 
-    let (a, b, c) = RE.captures(s).and_then(|cap| {
-        let a = cap.name("a").map(|v| v.as_str().parse::<usize>())?.ok()?;
-        let b = cap.name("b").map(|v| v.as_str().parse::<usize>())?.ok()?;
-        let c = cap.name("c").map(|v| v.as_str().parse::<usize>())?.ok()?;
+fn sorted(triangle: &Triangle) -> [u128; 3] {
+    let mut sides = triangle.map(|s| s as u128);
+    sides.sort();
+    sides
+}
 
-        Some((a, b, c))
-    }).ok_or(ParseError::new("Error during parse"))?;
+fn classify(triangle: &Triangle) -> Class {
+    let [a, b, c] = sorted(triangle);
 
-    Ok([a, b, c])
+    if a + b <= c {
+        Class::Degenerate
+    } else if a == c {
+        Class::Equilateral
+    } else if a == b || b == c {
+        Class::Isosceles
+    } else if is_right(triangle) {
+        Class::Right
+    } else {
+        Class::Scalene
+    }
 }
 
-#[aoc_generator(day3)]
-fn get_input(input: &str) -> Result<Vec<Triangle>, ParseError> {
-    Ok(input
-        .lines()
-        .map(|t| parse_triangle(t))
-        .collect::<Result<Vec<_>, ParseError>>()?)
+fn is_right(triangle: &Triangle) -> bool {
+    let [a, b, c] = sorted(triangle);
+
+    a + b > c
+        && a.checked_mul(a).zip(b.checked_mul(b)).and_then(|(a, b)| a.checked_add(b)) == c.checked_mul(c)
 }
 
-fn is_valid(triangle: &Triangle) -> bool {
-    let a = triangle[0];
-    let b = triangle[1];
-    let c = triangle[2];
+fn perimeter(triangle: &Triangle) -> u128 {
+    sorted(triangle).iter().sum()
+}
+
+// Heron's formula as 16 A^2 = (a + b + c)(-a + b + c)(a - b + c)(a + b - c),
+// which stays in integers until the square root is taken. There is no area
+// if the sides can't even form a flat triangle.
+fn area(triangle: &Triangle) -> Option<Area> {
+    let [a, b, c] = sorted(triangle);
+    if a + b < c {
+        return None;
+    }
+
+    let factors = [a + b + c, b + c - a, a + c - b, a + b - c];
+    let squared = factors.iter().try_fold(1u128, |p, f| p.checked_mul(*f));
 
-    a < b + c && b < a + c && c < a + b
+    Some(match squared {
+        Some(squared) if squared.isqrt() * squared.isqrt() == squared => Area::Exact(squared.isqrt()),
+        Some(squared) => Area::Approximate((squared as f64).sqrt() / 4.0),
+        None => Area::Approximate(factors.iter().map(|f| (*f as f64).sqrt()).product::<f64>() / 4.0),
+    })
 }
 
-#[aoc(day3, part1)]
-fn problem1(input: &Vec<Triangle>) -> Result<usize, ParseError> {
-    Ok(input.iter().filter(|t| is_valid(t)).count())
+// Number of triangles per class and a few totals over all proper triangles
+#[derive(Debug, Default, PartialEq)]
+struct Report {
+    classes: BTreeMap<Class, usize>,
+    perimeter: u128,
+    largest: Option<(Triangle, Area)>,
+}
+
+impl Report {
+    fn new(triangles: &[Triangle]) -> Self {
+        let mut report = Self::default();
+
+        for t in triangles {
+            *report.classes.entry(classify(t)).or_insert(0) += 1;
+
+            if is_valid(t) {
+                report.perimeter += perimeter(t);
+            }
+
+            if let Some(area) = area(t) {
+                let larger = match &report.largest {
+                    None => true,
+                    Some((_, largest)) => value(&area) > value(largest),
+                };
+                if larger {
+                    report.largest = Some((*t, area));
+                }
+            }
+        }
+
+        report
+    }
+
+    fn valid(&self) -> usize {
+        self.classes.iter()
+            .filter(|(class, _)| **class > Class::Degenerate)
+            .map(|(_, n)| n)
+            .sum()
+    }
 }
 
-fn transform(triangles_in: &Vec<Triangle>) -> Vec<Triangle> {
-    let mut result = vec![];
+fn value(area: &Area) -> f64 {
+    match area {
+        Area::Exact(quarters) => *quarters as f64 / 4.0,
+        Area::Approximate(area) => *area,
+    }
+}
 
-    for t in (0..triangles_in.len()).step_by(3) {
-        for i in 0..3 {
-            let new_t = [triangles_in[t][i], triangles_in[t+1][i], triangles_in[t+2][i]];
-            result.push(new_t);
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for class in CLASSES {
+            writeln!(f, "{:<12} {:>8}", format!("{:?}", class), self.classes.get(&class).unwrap_or(&0))?;
+        }
+        writeln!(f, "{:<12} {:>8}", "Perimeter", self.perimeter)?;
+
+        match &self.largest {
+            Some((t, area)) => writeln!(f, "{:<12} {:>8} {:?}", "Largest", area.to_string(), t),
+            None => writeln!(f, "{:<12} {:>8}", "Largest", "-"),
         }
     }
+}
 
-    result
+#[aoc(day3, part1)]
+fn problem1(input: &[Triangle]) -> Result<usize, ParseError> {
+    Ok(Report::new(input).valid())
+}
+
+// The triangles are actually listed vertically in groups of three rows
+fn transform(triangles_in: &[Triangle]) -> Result<Vec<Triangle>, ParseError> {
+    group(triangles_in, Order::ColumnMajor)
 }
 
 #[aoc(day3, part2)]
-fn problem2(input: &Vec<Triangle>) -> Result<usize, ParseError> {
-    let triangles = transform(input);
-    Ok(triangles.iter().filter(|t| is_valid(t)).count())
+fn problem2(input: &[Triangle]) -> Result<usize, ParseError> {
+    Ok(Report::new(&transform(input)?).valid())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input("5 10 25\n3 4 5\n6 8 10")?;
+
+        assert_eq!(2, problem1(&input)?);
+        assert_eq!(vec![[5, 3, 6], [10, 4, 8], [25, 5, 10]], transform(&input)?);
+        assert_eq!(2, problem2(&input)?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn invalid_input() {
+        assert!(get_input("1 2\n").is_err());
+        assert!(get_input("1 2 -3\n").is_err());
+        assert!(transform(&get_input("1 2 3\n4 5 6").unwrap()).is_err());
+    }
+
+    #[test]
+    pub fn classification() {
+        assert_eq!(Class::Degenerate, classify(&[1, 5, 3]));
+        assert_eq!(Class::Degenerate, classify(&[2, 5, 3]));
+        assert_eq!(Class::Scalene, classify(&[4, 5, 2]));
+        assert_eq!(Class::Right, classify(&[4, 5, 3]));
+        assert_eq!(Class::Isosceles, classify(&[5, 5, 3]));
+        assert_eq!(Class::Equilateral, classify(&[5, 5, 5]));
+        assert!(is_right(&[5, 3, 4]) && !is_right(&[5, 5, 5]) && !is_right(&[0, 0, 0]));
+        assert_eq!(12, perimeter(&[5, 3, 4]));
+    }
+
+    #[test]
+    pub fn heron() {
+        assert_eq!(Some(Area::Exact(24)), area(&[5, 3, 4]));
+        assert_eq!(Some(Area::Exact(0)), area(&[1, 1, 2]));
+        assert_eq!(None, area(&[1, 1, 3]));
+        assert_eq!("6", area(&[5, 3, 4]).unwrap().to_string());
+        assert_eq!("~0.433", area(&[1, 1, 1]).unwrap().to_string());
+
+        // the product overflows, but the approximation still works
+        let big = usize::MAX / 4;
+        assert!(matches!(area(&[big, big, big]), Some(Area::Approximate(a)) if a > 1e36));
+    }
+
+    #[test]
+    pub fn report() -> Result<(), ParseError> {
+        let report = Report::new(&get_input("3 4 5\n5 5 5\n1 1 3\n1 2 3")?);
+
+        assert_eq!(1, report.classes[&Class::Equilateral]);
+        assert_eq!(1, report.classes[&Class::Right]);
+        assert_eq!(2, report.classes[&Class::Degenerate]);
+        assert_eq!(2, report.valid());
+        assert_eq!(27, report.perimeter);
+        assert_eq!(Some(([5, 5, 5], area(&[5, 5, 5]).unwrap())), report.largest);
+        assert!(report.to_string().starts_with("Degenerate          2\nScalene             0\nRight               1\n"));
+        Ok(())
+    }
 }
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use regex::Regex;
 use std::cmp::Ordering;
+use std::fmt;
 use std::str::FromStr;
 use crate::utils::ParseError;
 
@@ -31,6 +32,12 @@ impl FromStr for Entry {
     }
 }
 
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}[{}]", self.name, self.sector, self.checksum)
+    }
+}
+
 impl Entry {
     fn new(name: &str, sector: usize, checksum: &str) -> Self {
         let name = name.to_string();
@@ -39,6 +46,44 @@ impl Entry {
         Self { name, sector, checksum }
     }
 
+    // Encrypts a plaintext name of lower case words separated by spaces into
+    // a real room. The name needs at least five different letters, otherwise
+    // there is no valid checksum.
+    #[allow(dead_code)]
+    fn encrypt(plaintext: &str, sector: usize) -> Result<Self, ParseError> {
+        let rot = 26 - sector % 26;
+        let name = plaintext.trim()
+            .split(' ')
+            .map(|word| {
+                if word.is_empty() {
+                    Err(ParseError::new(&format!("Empty word in {}", plaintext)))?;
+                }
+                word.chars().map(|c| Self::shift(c, rot)).collect::<Result<String, ParseError>>()
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?
+            .join("-");
+
+        let mut entry = Self::new(&name, sector, "");
+        entry.checksum = entry.checksum();
+
+        if entry.checksum.len() < 5 {
+            Err(ParseError::new(&format!("{} has less than five different letters", plaintext)))?;
+        }
+
+        Ok(entry)
+    }
+
+    // Same as a real room, but with a checksum that doesn't verify. The five
+    // letters of the checksum are all different, so reversing them always
+    // gives a wrong one.
+    #[allow(dead_code)]
+    fn decoy(plaintext: &str, sector: usize) -> Result<Self, ParseError> {
+        let mut entry = Self::encrypt(plaintext, sector)?;
+        entry.checksum = entry.checksum.chars().rev().collect::<String>();
+
+        Ok(entry)
+    }
+
     fn checksum(&self) -> String {
         let mut histogram = HashMap::new();
         let chars = self.name.chars().filter(|c| *c != '-');
@@ -66,18 +111,80 @@ impl Entry {
         check == self.checksum
     }
 
-    fn shift(c: char, rot: usize) -> char {
-        (((((c as u8 - 97) as usize) + rot) % 26) as u8 + 97) as char
+    fn shift(c: char, rot: usize) -> Result<char, ParseError> {
+        if !c.is_ascii_lowercase() {
+            Err(ParseError::new(&format!("Cannot shift {}, only a-z are allowed", c)))?;
+        }
+
+        Ok((((((c as u8 - b'a') as usize) + rot % 26) % 26) as u8 + b'a') as char)
     }
 
-    fn decrypt(&self) -> String {
+    fn decrypt(&self) -> Result<String, ParseError> {
         self.name.chars().map(|c| if c == '-' {
-            ' '
+            Ok(' ')
         } else {
             Self::shift(c, self.sector)
         })
-        .collect::<String>()
+        .collect::<Result<String, ParseError>>()
+    }
+}
+
+// What to look for in the decrypted room names
+enum Query {
+    // any occurrence of the keyword, also inside of words
+    Keyword(String),
+    Pattern(Regex),
+    // whole words of the name that are in the list
+    Words(Vec<String>),
+}
+
+impl Query {
+    #[allow(dead_code)]
+    fn regex(pattern: &str) -> Result<Self, ParseError> {
+        Regex::new(pattern)
+            .map(Query::Pattern)
+            .map_err(|e| ParseError::new(&format!("Invalid pattern {}: {}", pattern, e)))
+    }
+
+    #[allow(dead_code)]
+    fn words(list: &str) -> Self {
+        Query::Words(list.split_whitespace().map(|w| w.to_lowercase()).collect::<Vec<_>>())
+    }
+
+    // Number of matches in the name, zero if it doesn't match at all
+    fn score(&self, name: &str) -> usize {
+        match self {
+            Query::Keyword(keyword) if keyword.is_empty() => 0,
+            Query::Keyword(keyword) => name.matches(keyword.as_str()).count(),
+            Query::Pattern(re) => re.find_iter(name).filter(|m| !m.as_str().is_empty()).count(),
+            Query::Words(list) => name.split(' ').filter(|w| list.iter().any(|l| l == w)).count(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Hit {
+    sector: usize,
+    name: String,
+    score: usize,
+}
+
+// All real rooms that match the query, best matches first and rooms with
+// the same score ordered by sector
+fn search(rooms: &[Entry], query: &Query) -> Result<Vec<Hit>, ParseError> {
+    let mut hits = vec![];
+
+    for room in rooms.iter().filter(|r| r.verify()) {
+        let name = room.decrypt()?;
+        let score = query.score(&name);
+
+        if score > 0 {
+            hits.push(Hit { sector: room.sector, name, score });
+        }
     }
+
+    hits.sort_by(|a, b| b.score.cmp(&a.score).then(a.sector.cmp(&b.sector)));
+    Ok(hits)
 }
 
 #[aoc_generator(day4)]
@@ -99,12 +206,83 @@ fn problem1(input: &Vec<Entry>) -> Result<usize, ParseError> {
 
 #[aoc(day4, part2)]
 fn problem2(input: &Vec<Entry>) -> Result<usize, ParseError> {
-    let north = input
-        .iter()
-        .filter(|r| r.verify())
-        .filter(|r| r.decrypt().contains("orth"))
-        .next()
-        .ok_or(ParseError::new("No room with 'north' in its name found"))?;
+    search(input, &Query::Keyword("northpole".to_string()))?
+        .first()
+        .map(|hit| hit.sector)
+        .ok_or(ParseError::new("No room with 'northpole' in its name found"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    Ok(north.sector)
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example"))?;
+
+        assert_eq!(1514, problem1(&input)?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn example_2() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example-2"))?;
+
+        assert_eq!("very encrypted name", input[0].decrypt()?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn shift() {
+        assert_eq!('b', Entry::shift('z', 28).unwrap());
+        assert!(Entry::shift('A', 1).is_err());
+        assert!(Entry::shift('1', 1).is_err());
+        assert!(Entry::new("ab-C", 1, "abcde").decrypt().is_err());
+    }
+
+    #[test]
+    pub fn encrypt() -> Result<(), ParseError> {
+        let entry = Entry::encrypt("very encrypted name", 343)?;
+        assert_eq!("qzmt-zixmtkozy-ivhz-343[zimth]", entry.to_string());
+
+        for sector in [0, 1, 25, 26, 999] {
+            let entry = Entry::from_str(&Entry::encrypt("northpole object storage", sector)?.to_string())?;
+            assert!(entry.verify());
+            assert_eq!("northpole object storage", entry.decrypt()?);
+
+            let decoy = Entry::from_str(&Entry::decoy("northpole object storage", sector)?.to_string())?;
+            assert!(!decoy.verify());
+            assert_eq!("northpole object storage", decoy.decrypt()?);
+        }
+
+        assert!(Entry::encrypt("aaa bbb", 1).is_err());
+        assert!(Entry::encrypt("Upper case", 1).is_err());
+        assert!(Entry::encrypt("two  spaces", 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn search_rooms() -> Result<(), ParseError> {
+        let rooms = [
+            Entry::encrypt("northpole object storage", 20)?,
+            Entry::encrypt("object storage for rabbits", 10)?,
+            Entry::decoy("object storage deluxe", 5)?,
+            Entry::encrypt("candy cane reports", 30)?,
+            Entry::encrypt("weird object object", 40)?,
+        ];
+
+        let hits = search(&rooms, &Query::Keyword("object storage".to_string()))?;
+        assert_eq!(vec![10, 20], hits.iter().map(|h| h.sector).collect::<Vec<_>>());
+        assert_eq!("object storage for rabbits", hits[0].name);
+
+        let hits = search(&rooms, &Query::words("object storage"))?;
+        assert_eq!(vec![(10, 2), (20, 2), (40, 2)], hits.iter().map(|h| (h.sector, h.score)).collect::<Vec<_>>());
+
+        let hits = search(&rooms, &Query::regex(r"\bc\w+")?)?;
+        assert_eq!(vec![(30, 2)], hits.iter().map(|h| (h.sector, h.score)).collect::<Vec<_>>());
+
+        assert!(Query::regex("(").is_err());
+        assert!(search(&rooms, &Query::Keyword(String::new()))?.is_empty());
+        Ok(())
+    }
 }
@@ -1,47 +1,373 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use crate::utils::ParseError;
+use rayon::prelude::*;
 
-#[aoc(day5, part1)]
-fn problem1(input: &str) -> Result<String, ParseError> {
-    let mut passcode = vec![];
+// Number of indices hashed in parallel before results are looked at
+const CHUNK_SIZE: usize = 1 << 18;
+// Where the progress of long searches is kept between runs
+const CACHE_DIR: &str = "cache";
+const LEN: usize = 8;
+// Set this environment variable to watch the password being decrypted
+const CINEMATIC: &str = "Y2016_CINEMATIC";
+
+// How the password is filled from interesting hashes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rule {
+    // the sixth character goes into the next free position
+    Sequential,
+    // the sixth character is the position, the seventh the character
+    Positional,
+}
+
+impl Rule {
+    // Position and character the hash fills in, if any
+    fn apply(&self, passcode: &[Option<char>], hash: &str) -> Option<(usize, char)> {
+        let mut chars = hash.chars().skip(5);
+
+        match self {
+            Rule::Sequential => {
+                let position = passcode.iter().position(|c| c.is_none())?;
+                Some((position, chars.next()?))
+            },
+            Rule::Positional => {
+                let position = chars.next()?.to_digit(10)? as usize;
+                if passcode.get(position)?.is_some() {
+                    return None;
+                }
+                Some((position, chars.next()?))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    // a character of the password was found
+    Hit { index: usize, position: usize, digit: char },
+    // sent after every chunk, `found` has an underscore for unknown characters
+    Progress { index: usize, rate: f64, found: String },
+}
+
+fn hash(door: &str, index: usize) -> String {
+    format!("{:x}", md5::compute(format!("{}{}", door, index)))
+}
+
+fn is_interesting(door: &str, index: usize) -> bool {
+    let digest = md5::compute(format!("{}{}", door, index));
+    digest[0] == 0 && digest[1] == 0 && digest[2] >> 4 == 0
+}
+
+fn checkpoint_file(dir: &Path, door: &str) -> PathBuf {
+    dir.join(format!("day5-{}.txt", door))
+}
+
+struct Miner {
+    door: String,
+    rule: Rule,
+    // everything below this index has been searched
+    next_index: usize,
+    // indices of all hashes starting with five zeros found so far, these
+    // don't depend on the rule, so both parts can share a checkpoint
+    hits: Vec<usize>,
+    checkpoint: Option<PathBuf>,
+}
+
+impl Miner {
+    fn new(door: &str, rule: Rule) -> Self {
+        let door = door.trim().to_string();
+
+        Self { door, rule, next_index: 0, hits: vec![], checkpoint: None }
+    }
+
+    // Resumes the search of an earlier run from the checkpoint in `dir`, if
+    // there is one. The checkpoint is updated after every chunk. Stored hits
+    // are checked again and a damaged file is ignored as a whole, as the
+    // search would otherwise skip over the hits that went missing.
+    fn with_checkpoint(door: &str, rule: Rule, dir: &Path) -> io::Result<Self> {
+        let mut miner = Self::new(door, rule);
+        let file = checkpoint_file(dir, &miner.door);
+
+        if file.exists() {
+            let numbers = fs::read_to_string(&file)?
+                .lines()
+                .map(|l| l.parse::<usize>().ok())
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_default();
 
-    for i in 0.. {
-        let str = format!("{}{}", input, i);
-        let hash = format!("{:x}", md5::compute(str.as_bytes()));
+            if let Some((next_index, hits)) = numbers.split_first() {
+                if hits.iter().all(|i| i < next_index && is_interesting(&miner.door, *i)) {
+                    miner.next_index = *next_index;
+                    miner.hits = hits.to_vec();
+                }
+            }
+        }
+        miner.checkpoint = Some(file);
+
+        Ok(miner)
+    }
+
+    // Writes a temporary file first and moves it over the checkpoint, so an
+    // interrupted run leaves either the old or the new checkpoint behind
+    fn save(&self) -> io::Result<()> {
+        if let Some(file) = &self.checkpoint {
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)?;
+            }
 
-        if hash.chars().take(5).collect::<String>() == "00000" {
-            let next = hash.chars().skip(5).next().ok_or(ParseError::new("Could not fetch next char"))?;
-            passcode.push(next);
+            let content = std::iter::once(self.next_index)
+                .chain(self.hits.iter().cloned())
+                .map(|i| format!("{}\n", i))
+                .collect::<String>();
+            let temp = file.with_extension("tmp");
+            fs::write(&temp, content)?;
+            fs::rename(&temp, file)?;
         }
 
-        if passcode.len() == 8 {
-            break;
+        Ok(())
+    }
+
+    // Feeds a hit to the rule and tells the world if it was any good
+    fn fill<F: FnMut(&Event)>(&self, passcode: &mut [Option<char>], index: usize, on_event: &mut F) {
+        if let Some((position, digit)) = self.rule.apply(passcode, &hash(&self.door, index)) {
+            passcode[position] = Some(digit);
+            on_event(&Event::Hit { index, position, digit });
         }
     }
 
-    Ok(passcode.iter().collect::<String>())
+    // Searches all indices below `until` in chunks, each chunk in parallel,
+    // and returns the password once it is complete. Hits from earlier runs
+    // are replayed first, so the events always tell the whole story.
+    fn mine<F: FnMut(&Event)>(&mut self, until: usize, mut on_event: F) -> io::Result<Option<String>> {
+        let mut passcode = [None; LEN];
+        let started = Instant::now();
+        let first = self.next_index;
+
+        for &index in &self.hits {
+            self.fill(&mut passcode, index, &mut on_event);
+        }
+
+        while passcode.contains(&None) && self.next_index < until {
+            let start = self.next_index;
+            let end = until.min(start.saturating_add(CHUNK_SIZE));
+            let door = self.door.as_str();
+
+            // collect keeps the index order, no matter which thread was first
+            let hits = (start..end)
+                .into_par_iter()
+                .filter(|i| is_interesting(door, *i))
+                .collect::<Vec<_>>();
+
+            for &index in &hits {
+                if passcode.contains(&None) {
+                    self.fill(&mut passcode, index, &mut on_event);
+                }
+            }
+            self.hits.extend(hits);
+            self.next_index = end;
+            self.save()?;
+
+            let found = passcode.iter().map(|c| c.unwrap_or('_')).collect::<String>();
+            let rate = (end - first) as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON);
+            on_event(&Event::Progress { index: end, rate, found });
+        }
+
+        if passcode.contains(&None) {
+            return Ok(None);
+        }
+
+        Ok(Some(passcode.iter().flatten().collect::<String>()))
+    }
+
+    fn run<F: FnMut(&Event)>(&mut self, on_event: F) -> Result<String, ParseError> {
+        self.mine(usize::MAX, on_event)
+            .map_err(|e| ParseError::new(&format!("Could not write checkpoint: {}", e)))?
+            .ok_or(ParseError::new("Ran out of indices"))
+    }
+}
+
+enum Screen {
+    Terminal,
+    Recording(Vec<String>),
+}
+
+// Renders the password while it's mined, unknown characters flicker through
+// random hex digits and found ones lock in. Feed it the miner's events.
+struct Cinematic {
+    locked: [Option<char>; LEN],
+    seed: u64,
+    screen: Screen,
+}
+
+impl Cinematic {
+    fn terminal(seed: u64) -> Self {
+        Self { locked: [None; LEN], seed, screen: Screen::Terminal }
+    }
+
+    #[allow(dead_code)]
+    fn recording(seed: u64) -> Self {
+        Self { locked: [None; LEN], seed, screen: Screen::Recording(vec![]) }
+    }
+
+    fn random_digit(&mut self) -> char {
+        self.seed = self.seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        char::from_digit(((self.seed >> 33) % 16) as u32, 16).unwrap_or('0')
+    }
+
+    fn frame(&mut self) -> String {
+        (0..LEN)
+            .map(|i| match self.locked[i] {
+                Some(c) => c,
+                None => self.random_digit(),
+            })
+            .collect::<String>()
+    }
+
+    fn show(&mut self, e: &Event) -> io::Result<()> {
+        if let Event::Hit { position, digit, .. } = e {
+            self.locked[*position] = Some(*digit);
+        }
+
+        let frame = self.frame();
+        let done = !self.locked.contains(&None);
+
+        match &mut self.screen {
+            Screen::Terminal => {
+                let mut out = io::stdout();
+                write!(out, "\r{}", frame)?;
+                if done {
+                    writeln!(out)?;
+                }
+                out.flush()
+            },
+            Screen::Recording(frames) => {
+                frames.push(frame);
+                Ok(())
+            },
+        }
+    }
+
+    #[allow(dead_code)]
+    fn frames(&self) -> &[String] {
+        match &self.screen {
+            Screen::Terminal => &[],
+            Screen::Recording(frames) => frames,
+        }
+    }
+}
+
+fn solve(input: &str, rule: Rule) -> Result<String, ParseError> {
+    let mut miner = Miner::with_checkpoint(input, rule, Path::new(CACHE_DIR))
+        .map_err(|e| ParseError::new(&format!("Could not read checkpoint: {}", e)))?;
+
+    if std::env::var_os(CINEMATIC).is_none() {
+        return miner.run(|_| {});
+    }
+
+    // the show must go on, even if the terminal is gone
+    let mut cinematic = Cinematic::terminal(input.len() as u64);
+    miner.run(|e| { cinematic.show(e).ok(); })
+}
+
+#[aoc(day5, part1)]
+fn problem1(input: &str) -> Result<String, ParseError> {
+    solve(input, Rule::Sequential)
 }
 
 #[aoc(day5, part2)]
 fn problem2(input: &str) -> Result<String, ParseError> {
-    let mut passcode = vec![' '; 8];
+    solve(input, Rule::Positional)
+}
 
-    for i in 0.. {
-        let str = format!("{}{}", input, i);
-        let hash = format!("{:x}", md5::compute(str.as_bytes()));
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        if hash.chars().take(5).collect::<String>() == "00000" {
-            let next = hash.chars().skip(6).next().ok_or(ParseError::new("Could not fetch next char"))?;
-            if let Some(pos) = hash.chars().skip(5).next().ok_or(ParseError::new("Could not fetch next char pos"))?.to_digit(10) {
-                if pos < 8 && passcode[pos as usize] == ' ' {
-                    passcode[pos as usize] = next;
-                }
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let mut hits = vec![];
+        let password = Miner::new(include_str!("example"), Rule::Sequential).run(|e| {
+            if let Event::Hit { index, .. } = e {
+                hits.push(*index);
             }
-        }
+        })?;
+
+        assert_eq!("18f47a30", password);
+        assert_eq!(vec![3231929, 5017308, 5278568], hits[..3]);
+        Ok(())
+    }
+
+    #[test]
+    pub fn example_2() -> Result<(), ParseError> {
+        assert_eq!("05ace8e3", Miner::new(include_str!("example"), Rule::Positional).run(|_| {})?);
+        Ok(())
+    }
 
-        if !passcode.contains(&' ') {
-            break;
+    #[test]
+    pub fn resume() -> io::Result<()> {
+        let dir = std::env::temp_dir().join("y2016-day5-test");
+        let file = checkpoint_file(&dir, "abc");
+        if file.exists() {
+            fs::remove_file(&file)?;
         }
+
+        let mut miner = Miner::with_checkpoint("abc", Rule::Sequential, &dir)?;
+        let mut progress = vec![];
+        let partial = miner.mine(3_300_000, |e| {
+            if let Event::Progress { index, found, .. } = e {
+                progress.push((*index, found.clone()));
+            }
+        })?;
+        assert_eq!(None, partial);
+        assert_eq!(Some(&(3_300_000, "1_______".to_string())), progress.last());
+
+        // the second run starts where the first one stopped
+        let mut resumed = Miner::with_checkpoint("abc", Rule::Positional, &dir)?;
+        assert_eq!((3_300_000, vec![3231929]), (resumed.next_index, resumed.hits.clone()));
+
+        let mut events = vec![];
+        assert_eq!(None, resumed.mine(3_300_001, |e| events.push(e.clone()))?);
+        assert!(matches!(events[0], Event::Hit { index: 3231929, position: 1, .. }));
+        assert!(matches!(events[1], Event::Progress { index: 3_300_001, .. }));
+        assert!(!file.with_extension("tmp").exists());
+
+        // a damaged checkpoint is searched again from the start
+        fs::write(&file, "3300000\n323\n3231929\n32319\n")?;
+        let damaged = Miner::with_checkpoint("abc", Rule::Sequential, &dir)?;
+        assert_eq!((0, vec![]), (damaged.next_index, damaged.hits));
+
+        fs::write(&file, "3300000\n3231\n")?;
+        let mut damaged = Miner::with_checkpoint("abc", Rule::Sequential, &dir)?;
+        assert_eq!(Ok("18f47a30".to_string()), damaged.run(|_| {}).map_err(|e| e.what));
+
+        fs::remove_file(&file)
     }
 
-    Ok(passcode.iter().collect::<String>())
+    #[test]
+    pub fn cinematic() -> io::Result<()> {
+        let mut cinematic = Cinematic::recording(1);
+        let events = [
+            Event::Progress { index: 10, rate: 1.0, found: "________".to_string() },
+            Event::Hit { index: 12, position: 3, digit: 'x' },
+            Event::Progress { index: 20, rate: 1.0, found: "___x____".to_string() },
+        ];
+        for e in &events {
+            cinematic.show(e)?;
+        }
+        for position in 0..LEN {
+            cinematic.show(&Event::Hit { index: 30, position, digit: 'a' })?;
+        }
+
+        let frames = cinematic.frames();
+        assert_eq!(3 + LEN, frames.len());
+        assert!(frames.iter().all(|f| f.len() == LEN));
+        assert!(frames[0].chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(Some('x'), frames[1].chars().nth(3));
+        assert_eq!(Some('x'), frames[2].chars().nth(3));
+        assert_ne!(frames[1], frames[2]);
+        assert_eq!("aaaaaaaa", frames[frames.len() - 1]);
+        Ok(())
+    }
 }
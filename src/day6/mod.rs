@@ -1,36 +1,244 @@
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::io::BufRead;
 use crate::utils::ParseError;
 
 #[aoc_generator(day6)]
-fn get_input(input: &str) -> Result<Vec<Vec<char>>, ParseError> {
-    Ok(input
-        .lines()
-        .map(|l| l.chars().collect::<Vec<_>>())
-        .collect::<Vec<_>>())
+fn get_input(input: &str) -> Result<Histogram, ParseError> {
+    Histogram::from_reader(input.as_bytes(), Lines::Strict)
 }
 
-type Comparer = fn(&(char, usize), &(char, usize)) -> Ordering;
-fn decode_pos(input: &Vec<Vec<char>>, pos: usize, cmp: Comparer) -> char {
-    let mut histogram: HashMap<char, usize> = HashMap::new();
-    for line in input {
-        let c = line[pos];
-        histogram.entry(c).and_modify(|v| *v += 1).or_insert(1);
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    MostCommon,
+    LeastCommon,
+}
+
+// What to do with lines that are shorter or longer than the others
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lines {
+    // every line must be as long as the first one
+    Strict,
+    // lines only vote for the columns they have, empty lines are skipped
+    #[allow(dead_code)]
+    Ragged,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Column {
+    chosen: char,
+    count: usize,
+    runner_up: Option<(char, usize)>,
+    // how many votes separate the chosen character from the runner up, the
+    // count itself if there is no runner up
+    margin: usize,
+}
+
+impl Column {
+    fn new((chosen, count): (u8, u32), runner_up: Option<(u8, u32)>) -> Self {
+        let margin = runner_up.map(|(_, n)| count.abs_diff(n)).unwrap_or(count) as usize;
+        let runner_up = runner_up.map(|(c, n)| (c as char, n as usize));
+
+        Self { chosen: chosen as char, count: count as usize, runner_up, margin }
+    }
+}
+
+type Top = (Option<(u8, u32)>, Option<(u8, u32)>);
+
+// Keeps the best two entries according to `better`
+fn rank<F>(top: &mut Top, entry: (u8, u32), better: F)
+where F: Fn(u32, u32) -> bool {
+    match *top {
+        (None, _) => top.0 = Some(entry),
+        (Some(first), _) if better(entry.1, first.1) => *top = (Some(entry), Some(first)),
+        (_, None) => top.1 = Some(entry),
+        (_, Some(second)) if better(entry.1, second.1) => top.1 = Some(entry),
+        _ => {},
+    }
+}
+
+// Per column counters for every byte. Lines are added one at a time, so the
+// input never has to be in memory as a whole.
+#[derive(Debug)]
+struct Histogram {
+    columns: Vec<[u32; 256]>,
+    lines: Lines,
+    width: Option<usize>,
+    line: usize,
+}
+
+impl Histogram {
+    fn new(lines: Lines) -> Self {
+        Self { columns: vec![], lines, width: None, line: 0 }
+    }
+
+    fn from_reader<R: BufRead>(mut reader: R, lines: Lines) -> Result<Self, ParseError> {
+        let mut histogram = Self::new(lines);
+        let mut buffer = vec![];
+
+        loop {
+            buffer.clear();
+            let read = reader.read_until(b'\n', &mut buffer)
+                .map_err(|e| ParseError::new(&format!("Could not read line {}: {}", histogram.line + 1, e)))?;
+            if read == 0 {
+                break;
+            }
+
+            histogram.add(&buffer)?;
+        }
+
+        if histogram.columns.is_empty() {
+            Err(ParseError::new("No message to decode"))?;
+        }
+
+        Ok(histogram)
+    }
+
+    fn add(&mut self, line: &[u8]) -> Result<(), ParseError> {
+        self.line += 1;
+
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if self.lines == Lines::Strict {
+            let width = *self.width.get_or_insert(line.len());
+            if line.len() != width {
+                Err(ParseError::new(&format!("Line {} has {} characters instead of {}", self.line, line.len(), width)))?;
+            }
+        }
+
+        if let Some(c) = line.iter().find(|c| !c.is_ascii()) {
+            Err(ParseError::new(&format!("Line {} contains the non-ASCII byte {:#x}", self.line, c)))?;
+        }
+
+        if self.columns.len() < line.len() {
+            self.columns.resize(line.len(), [0; 256]);
+        }
+        for (column, c) in self.columns.iter_mut().zip(line) {
+            column[*c as usize] = column[*c as usize].checked_add(1)
+                .ok_or(ParseError::new(&format!("Too many votes in line {}", self.line)))?;
+        }
+
+        Ok(())
     }
-    let mut freq = histogram.into_iter().collect::<Vec<_>>();
-    freq.sort_by(cmp);
 
-    freq[0].0
+    // Most and least common columns at once. Bytes are visited in ascending
+    // order and only replace a leader if they are strictly better, so ties
+    // always go to the smallest character.
+    fn decode_both(&self) -> (Vec<Column>, Vec<Column>) {
+        self.columns.iter()
+            .map(|counts| {
+                let mut most: Top = (None, None);
+                let mut least: Top = (None, None);
+
+                for (c, n) in (0..=255u8).zip(counts.iter().cloned()).filter(|(_, n)| *n > 0) {
+                    rank(&mut most, (c, n), |a, b| a > b);
+                    rank(&mut least, (c, n), |a, b| a < b);
+                }
+
+                // a column only exists because some line was long enough, so
+                // there is always at least one vote
+                let most = Column::new(most.0.unwrap_or((b' ', 0)), most.1);
+                let least = Column::new(least.0.unwrap_or((b' ', 0)), least.1);
+                (most, least)
+            })
+            .unzip()
+    }
+
+    fn decode(&self, mode: Mode) -> Vec<Column> {
+        let (most, least) = self.decode_both();
+
+        match mode {
+            Mode::MostCommon => most,
+            Mode::LeastCommon => least,
+        }
+    }
+}
+
+fn message(columns: &[Column]) -> String {
+    columns.iter().map(|c| c.chosen).collect::<String>()
 }
 
 #[aoc(day6, part1)]
-fn problem1(input: &Vec<Vec<char>>) -> Result<String, ParseError> {
-    let size = input[0].len();
-    Ok((0..size).map(|p| decode_pos(input, p, |a, b| b.1.cmp(&a.1))).collect::<String>())
+fn problem1(input: &Histogram) -> Result<String, ParseError> {
+    Ok(message(&input.decode(Mode::MostCommon)))
 }
 
 #[aoc(day6, part2)]
-fn problem2(input: &Vec<Vec<char>>) -> Result<String, ParseError> {
-    let size = input[0].len();
-    Ok((0..size).map(|p| decode_pos(input, p, |a, b| a.1.cmp(&b.1))).collect::<String>())
+fn problem2(input: &Histogram) -> Result<String, ParseError> {
+    Ok(message(&input.decode(Mode::LeastCommon)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    // Generates its lines while they are read
+    struct Log {
+        line: usize,
+        lines: usize,
+    }
+
+    impl Read for Log {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.line == self.lines {
+                return Ok(0);
+            }
+            self.line += 1;
+
+            // column 0 is mostly a, column 1 mostly b with a rare z
+            let line = if self.line.is_multiple_of(1000) { "cz\n" } else if self.line.is_multiple_of(3) { "xb\n" } else { "ab\n" };
+            buf[..line.len()].copy_from_slice(line.as_bytes());
+            Ok(line.len())
+        }
+    }
+
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example"))?;
+
+        assert_eq!("easter", problem1(&input)?);
+        assert_eq!("advent", problem2(&input)?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn scores() -> Result<(), ParseError> {
+        let input = get_input("ab\nab\nbb\nca")?;
+        let columns = input.decode(Mode::MostCommon);
+
+        assert_eq!(Column { chosen: 'a', count: 2, runner_up: Some(('b', 1)), margin: 1 }, columns[0]);
+        assert_eq!(Column { chosen: 'b', count: 3, runner_up: Some(('a', 1)), margin: 2 }, columns[1]);
+
+        // b and c are both least common, the smaller one wins
+        let columns = input.decode(Mode::LeastCommon);
+        assert_eq!(Column { chosen: 'b', count: 1, runner_up: Some(('c', 1)), margin: 0 }, columns[0]);
+        assert_eq!(Column { chosen: 'a', count: 1, runner_up: Some(('b', 3)), margin: 2 }, columns[1]);
+        Ok(())
+    }
+
+    #[test]
+    pub fn ragged() -> Result<(), ParseError> {
+        let input = "abc\n\na\nab\n";
+
+        assert!(Histogram::from_reader(input.as_bytes(), Lines::Strict).is_err());
+        let columns = Histogram::from_reader(input.as_bytes(), Lines::Ragged)?.decode(Mode::MostCommon);
+        assert_eq!("abc", message(&columns));
+        assert_eq!((1, None, 1), (columns[2].count, columns[2].runner_up, columns[2].margin));
+
+        assert!(Histogram::from_reader("".as_bytes(), Lines::Ragged).is_err());
+        assert!(Histogram::from_reader("\n\r\n".as_bytes(), Lines::Ragged).is_err());
+        assert!(Histogram::from_reader("ab\nä\n".as_bytes(), Lines::Ragged).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn streaming() -> Result<(), ParseError> {
+        let reader = std::io::BufReader::new(Log { line: 0, lines: 1_000_000 });
+        let (most, least) = Histogram::from_reader(reader, Lines::Strict)?.decode_both();
+
+        assert_eq!(("ab".to_string(), "cz".to_string()), (message(&most), message(&least)));
+        assert_eq!(Some(('x', 333_000)), most[0].runner_up);
+        assert_eq!(Column { chosen: 'z', count: 1000, runner_up: Some(('b', 999_000)), margin: 998_000 }, least[1]);
+        Ok(())
+    }
 }
@@ -1,154 +1,286 @@
-use std::collections::VecDeque;
 use std::fmt::Display;
+use std::ops::Range;
+use std::str::FromStr;
 use crate::utils::ParseError;
 
-#[derive(PartialEq)]
-enum State {
-    Parsing,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    // outside of square brackets
+    Supernet,
+    // inside of square brackets
     Hypernet,
 }
 
+impl Kind {
+    fn other(&self) -> Self {
+        match self {
+            Kind::Supernet => Kind::Hypernet,
+            Kind::Hypernet => Kind::Supernet,
+        }
+    }
+}
+
+// An address split into its sequences. Brackets must come in pairs, can't
+// be nested and can't be empty.
 #[derive(Debug)]
-struct Ip {
-    ip: String,
+struct Ipv7 {
+    address: String,
+    segments: Vec<(Kind, Range<usize>)>,
 }
 
-impl Ip {
-    fn new(ip: &str) -> Self {
-        Ip { ip: ip.to_string() }
+impl FromStr for Ipv7 {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, <Self as FromStr>::Err> {
+        let address = s.trim().to_string();
+        let mut segments = vec![];
+        let mut open = None;
+        let mut start = 0;
+
+        for (i, c) in address.char_indices() {
+            match (c, open) {
+                ('[', Some(o)) => Err(ParseError::new(&format!("Nested [ at position {} inside of [ at position {}", i, o)))?,
+                (']', None) => Err(ParseError::new(&format!("Unmatched ] at position {}", i)))?,
+                (']', Some(o)) if o + 1 == i => Err(ParseError::new(&format!("Empty hypernet sequence at position {}", o)))?,
+                ('[', None) => {
+                    segments.push((Kind::Supernet, start..i));
+                    open = Some(i);
+                    start = i + 1;
+                },
+                (']', Some(_)) => {
+                    segments.push((Kind::Hypernet, start..i));
+                    open = None;
+                    start = i + 1;
+                },
+                _ => {},
+            }
+        }
+
+        if let Some(o) = open {
+            Err(ParseError::new(&format!("Unclosed [ at position {}", o)))?;
+        }
+        segments.push((Kind::Supernet, start..address.len()));
+        segments.retain(|(_, r)| !r.is_empty());
+
+        Ok(Self { address, segments })
     }
 }
 
-impl Display for Ip {
+impl Display for Ipv7 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        f.write_str(self.ip.as_str())
+        f.write_str(self.address.as_str())
+    }
+}
+
+impl Ipv7 {
+    fn segments(&self) -> impl Iterator<Item = (Kind, &str)> + '_ {
+        self.segments.iter().map(|(kind, r)| (*kind, &self.address[r.clone()]))
+    }
+
+    fn of_kind(&self, kind: Kind) -> impl Iterator<Item = &str> + '_ {
+        self.segments().filter(move |(k, _)| *k == kind).map(|(_, s)| s)
+    }
+
+    #[allow(dead_code)]
+    fn supernets(&self) -> impl Iterator<Item = &str> + '_ {
+        self.of_kind(Kind::Supernet)
+    }
+
+    #[allow(dead_code)]
+    fn hypernets(&self) -> impl Iterator<Item = &str> + '_ {
+        self.of_kind(Kind::Hypernet)
     }
 }
 
 #[aoc_generator(day7)]
-fn get_input(input: &str) -> Result<Vec<Ip>, ParseError> {
-    Ok(input.lines().map(|l| Ip::new(l)).collect::<Vec<_>>())
+fn get_input(input: &str) -> Result<Vec<Ipv7>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(i, l)| Ipv7::from_str(l).map_err(|e| ParseError::new(&format!("{} in line {}", e, i + 1))))
+        .collect::<Result<Vec<_>, ParseError>>()
 }
 
-impl Ip {
-    fn supports_tls(&self) -> bool {
-        use State::*;
-
-        let mut state = Parsing;
-        let mut last_four = VecDeque::new();
-        let mut supports_in_parsing = false;
-        for c in self.ip.chars() {
-            if c == '[' {
-                state = Hypernet;
-                last_four.clear();
-            }
+// A sequence of `len` characters that reads the same backwards. With
+// `inner_differs` the outermost character must not appear anywhere between
+// the two outer ones, like the A in ABBA or ABA.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Palindrome {
+    len: usize,
+    inner_differs: bool,
+}
 
-            if c == ']' {
-                state = Parsing;
-                last_four.clear();
-            }
+const ABBA: Palindrome = Palindrome { len: 4, inner_differs: true };
+const ABA: Palindrome = Palindrome { len: 3, inner_differs: true };
 
-            last_four.push_back(c);
-            if last_four.len() > 4 {
-                last_four.pop_front();
-            }
+impl Palindrome {
+    fn is_match(&self, w: &[u8]) -> bool {
+        let n = w.len();
 
-            if last_four.len() == 4 {
-                if last_four[0] == last_four[3] && last_four[1] == last_four[2] && last_four[0] != last_four[1] {
-                    if state == Parsing {
-                        supports_in_parsing = true;
-                    }
-                    if state == Hypernet {
-                        return false;
-                    }
-                }
-            }
-        }
+        n == self.len
+            && (0..n / 2).all(|i| w[i] == w[n - 1 - i])
+            && (!self.inner_differs || n < 3 || !w[1..n - 1].contains(&w[0]))
+    }
+
+    fn matches<'a>(&self, segment: &'a str) -> impl Iterator<Item = &'a [u8]> + 'a {
+        let pattern = *self;
 
-        supports_in_parsing
+        // windows can't be empty, but then there's nothing to find anyway
+        segment.as_bytes()
+            .windows(self.len.max(1))
+            .filter(move |w| pattern.is_match(w))
     }
 }
 
-#[aoc(day7, part1)]
-fn problem1(input: &Vec<Ip>) -> Result<usize, ParseError> {
-    let result = input.iter().filter(|i| i.supports_tls()).count();
-    Ok(result)
+// The same palindrome with the outer character and its neighbour swapped
+// everywhere, e.g. BAB for ABA or BAAB for ABBA.
+fn mirror(w: &[u8]) -> Vec<u8> {
+    if w.len() < 2 {
+        return w.to_vec();
+    }
+
+    let (a, b) = (w[0], w[1]);
+    w.iter()
+        .map(|c| if *c == a { b } else if *c == b { a } else { *c })
+        .collect::<Vec<_>>()
 }
 
-#[derive(Debug)]
-struct Triplet {
-    outer: char,
-    inner: char,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rule {
+    // at least one segment of the kind contains the palindrome
+    Contains(Kind, Palindrome),
+    // no segment of the kind contains the palindrome
+    Lacks(Kind, Palindrome),
+    // a segment of the kind contains the palindrome and a segment of the
+    // other kind contains its mirror
+    Mirrored(Kind, Palindrome),
+}
+
+impl Rule {
+    fn holds(&self, ip: &Ipv7) -> bool {
+        let contains = |kind: Kind, pattern: &Palindrome| {
+            ip.of_kind(kind).any(|s| pattern.matches(s).next().is_some())
+        };
+
+        match self {
+            Rule::Contains(kind, pattern) => contains(*kind, pattern),
+            Rule::Lacks(kind, pattern) => !contains(*kind, pattern),
+            Rule::Mirrored(kind, pattern) => ip.of_kind(*kind)
+                .flat_map(|s| pattern.matches(s))
+                .any(|m| {
+                    let mirrored = mirror(m);
+                    ip.of_kind(kind.other()).any(|s| s.as_bytes().windows(m.len()).any(|w| w == mirrored))
+                }),
+        }
+    }
+}
+
+// An address supports a protocol if all of its rules hold
+struct Protocol {
+    rules: &'static [Rule],
+}
+
+const TLS: Protocol = Protocol { rules: &[Rule::Contains(Kind::Supernet, ABBA), Rule::Lacks(Kind::Hypernet, ABBA)] };
+const SSL: Protocol = Protocol { rules: &[Rule::Mirrored(Kind::Supernet, ABA)] };
+
+impl Protocol {
+    fn supports(&self, ip: &Ipv7) -> bool {
+        self.rules.iter().all(|r| r.holds(ip))
+    }
 }
 
-impl Triplet {
-    fn new(outer: char, inner: char) -> Self {
-        Self { outer, inner }
+impl Ipv7 {
+    fn supports_tls(&self) -> bool {
+        TLS.supports(self)
     }
 
-    fn matches(&self, o: &Triplet) -> bool {
-        self.outer == o.inner && self.inner == o.outer && self.outer != o.outer
+    fn supports_ssl(&self) -> bool {
+        SSL.supports(self)
     }
 }
 
-impl Ip {
-    fn collect_triplets(&self) -> (Vec<Triplet>, Vec<Triplet>) {
-        use State::*;
+#[aoc(day7, part1)]
+fn problem1(input: &[Ipv7]) -> Result<usize, ParseError> {
+    let result = input.iter().filter(|i| i.supports_tls()).count();
+    Ok(result)
+}
 
-        let mut supernet = vec![];
-        let mut hypernet = vec![];
+#[aoc(day7, part2)]
+fn problem2(input: &[Ipv7]) -> Result<usize, ParseError> {
+    let result = input.iter().filter(|i| i.supports_ssl()).count();
+    Ok(result)
+}
 
-        let mut state = Parsing;
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        let mut last_three = VecDeque::new();
-        for c in self.ip.chars() {
-            if c == '[' {
-                state = Hypernet;
-                last_three.clear();
-            }
+    #[test]
+    pub fn example_1() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example"))?;
 
-            if c == ']' {
-                state = Parsing;
-                last_three.clear();
-            }
+        assert_eq!(vec![true, false, false, true], input.iter().map(|i| i.supports_tls()).collect::<Vec<_>>());
+        assert_eq!(2, problem1(&input)?);
+        Ok(())
+    }
 
-            last_three.push_back(c);
-            if last_three.len() > 3 {
-                last_three.pop_front();
-            }
+    #[test]
+    pub fn example_2() -> Result<(), ParseError> {
+        let input = get_input(include_str!("example-2"))?;
 
-            if last_three.len() == 3 {
-                if last_three[0] == last_three[2] && last_three[0] != last_three[1] {
-                    let triplet = Triplet::new(last_three[0], last_three[1]);
-                    if state == Parsing {
-                        supernet.push(triplet);
-                    } else {
-                        hypernet.push(triplet);
-                    }
-                }
-            }
-        }
+        assert_eq!(vec![true, false, true, true], input.iter().map(|i| i.supports_ssl()).collect::<Vec<_>>());
+        assert_eq!(3, problem2(&input)?);
+        Ok(())
+    }
+
+    #[test]
+    pub fn segments() -> Result<(), ParseError> {
+        let ip = Ipv7::from_str("[ab]cd[ef]gh")?;
 
-        (supernet, hypernet)
+        assert_eq!(vec![(Kind::Hypernet, "ab"), (Kind::Supernet, "cd"), (Kind::Hypernet, "ef"), (Kind::Supernet, "gh")], ip.segments().collect::<Vec<_>>());
+        assert_eq!(vec!["cd", "gh"], ip.supernets().collect::<Vec<_>>());
+        assert_eq!(vec!["ab", "ef"], ip.hypernets().collect::<Vec<_>>());
+        assert_eq!("[ab]cd[ef]gh", ip.to_string());
+        Ok(())
     }
 
-    fn supports_ssl(&self) -> bool {
-        let (supernet, hypernet) = self.collect_triplets();
+    #[test]
+    pub fn malformed() {
+        let error = |s: &str| Ipv7::from_str(s).unwrap_err().what;
 
-        for s in supernet {
-            for h in &hypernet {
-                if s.matches(h) {
-                    return true;
-                }
-            }
-        }
+        assert_eq!("Nested [ at position 3 inside of [ at position 1", error("a[b[c]]"));
+        assert_eq!("Unmatched ] at position 2", error("ab]c"));
+        assert_eq!("Unclosed [ at position 2", error("ab[cd"));
+        assert_eq!("Empty hypernet sequence at position 2", error("ab[]cd"));
+        assert!(get_input("abba\nab]ba").unwrap_err().what.ends_with("in line 2"));
+    }
+
+    #[test]
+    pub fn palindromes() {
+        let found = |p: Palindrome, s: &'static str| p.matches(s).map(|m| String::from_utf8_lossy(m).to_string()).collect::<Vec<_>>();
+
+        assert_eq!(vec!["abba", "xyyx"], found(ABBA, "abbaaaaxyyx"));
+        assert_eq!(vec!["aba", "bab"], found(ABA, "abab"));
+        assert_eq!(vec!["abcba"], found(Palindrome { len: 5, inner_differs: true }, "abcbaxaxax"));
+        assert_eq!(vec!["abcba", "axaxa", "xaxax"], found(Palindrome { len: 5, inner_differs: false }, "abcbaxaxax"));
+        assert_eq!(vec!["aaaa"], found(Palindrome { len: 4, inner_differs: false }, "aaaa"));
+        assert!(found(Palindrome { len: 0, inner_differs: true }, "abc").is_empty());
 
-        false
+        assert_eq!(b"bab".to_vec(), mirror(b"aba"));
+        assert_eq!(b"baab".to_vec(), mirror(b"abba"));
+        assert_eq!(b"bacab".to_vec(), mirror(b"abcba"));
     }
-}
 
-#[aoc(day7, part2)]
-fn problem2(input: &Vec<Ip>) -> Result<usize, ParseError> {
-    let result = input.iter().filter(|i| i.supports_ssl()).count();
-    Ok(result)
+    #[test]
+    pub fn custom_protocol() -> Result<(), ParseError> {
+        const FIVE: Protocol = Protocol { rules: &[
+            Rule::Mirrored(Kind::Hypernet, Palindrome { len: 5, inner_differs: true }),
+            Rule::Lacks(Kind::Supernet, ABBA),
+        ] };
+
+        assert!(FIVE.supports(&Ipv7::from_str("xbacabx[zabcbaz]")?));
+        assert!(!FIVE.supports(&Ipv7::from_str("xbacabx[zabcbaz]oxxo")?));
+        assert!(!FIVE.supports(&Ipv7::from_str("abcbb[bacab]")?));
+        Ok(())
+    }
 }
@@ -7,7 +7,6 @@ extern crate aoc_runner_derive;
 extern crate lazy_static;
 extern crate regex;
 extern crate pathfinding;
-extern crate memoize;
 extern crate rayon;
 
 mod day1;
@@ -0,0 +1,239 @@
+use super::ParseError;
+
+const BITS: usize = u64::BITS as usize;
+
+// Compact vector of bits, bit i lives in word i / 64 at position i % 64.
+// Bits beyond len in the last word are always zero.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BitVec {
+  words: Vec<u64>,
+  len: usize,
+}
+
+impl BitVec {
+  pub fn new(len: usize) -> Self {
+    let words = vec![0; len.div_ceil(BITS)];
+
+    Self { words, len }
+  }
+
+  // Parses a string of two different characters, e.g. "0110" or ".^^.".
+  pub fn parse(s: &str, zero: char, one: char) -> Result<Self, ParseError> {
+    let mut bits = Self::new(s.chars().count());
+
+    for (i, c) in s.chars().enumerate() {
+      if c == one {
+        bits.set(i, true);
+      } else if c != zero {
+        Err(ParseError::new(&format!("Invalid character '{}' at position {}", c, i)))?;
+      }
+    }
+
+    Ok(bits)
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn get(&self, i: usize) -> bool {
+    self.words[i / BITS] & 1 << (i % BITS) != 0
+  }
+
+  pub fn set(&mut self, i: usize, value: bool) {
+    if value {
+      self.words[i / BITS] |= 1 << (i % BITS);
+    } else {
+      self.words[i / BITS] &= !(1 << (i % BITS));
+    }
+  }
+
+  pub fn count_ones(&self) -> usize {
+    self.words.iter().map(|w| w.count_ones() as usize).sum()
+  }
+
+  // Number of ones among the first n bits
+  pub fn count_ones_before(&self, n: usize) -> usize {
+    let full = n / BITS;
+    let ones = self.words[..full].iter().map(|w| w.count_ones() as usize).sum::<usize>();
+
+    match n % BITS {
+      0 => ones,
+      rest => ones + (self.words[full] & ((1 << rest) - 1)).count_ones() as usize,
+    }
+  }
+
+  pub fn invert(&mut self) {
+    for w in self.words.iter_mut() {
+      *w = !*w;
+    }
+    self.clear_tail();
+  }
+
+  pub fn reverse(&mut self) {
+    let pad = self.words.len() * BITS - self.len;
+
+    self.words.reverse();
+    for w in self.words.iter_mut() {
+      *w = w.reverse_bits();
+    }
+
+    // the reversed bits are now aligned to the end of the last word
+    if pad > 0 {
+      for i in 0..self.words.len() {
+        let next = self.words.get(i + 1).cloned().unwrap_or(0);
+        self.words[i] = self.words[i] >> pad | next << (BITS - pad);
+      }
+    }
+  }
+
+  // Bit i of the result is bit i - 1 xor bit i + 1, bits outside of the
+  // vector count as zero.
+  pub fn xor_neighbours(&self) -> Self {
+    let n = self.words.len();
+    let mut words = vec![0; n];
+
+    for (i, word) in words.iter_mut().enumerate() {
+      let w = self.words[i];
+      let lower = if i > 0 { self.words[i - 1] >> (BITS - 1) } else { 0 };
+      let upper = if i + 1 < n { self.words[i + 1] << (BITS - 1) } else { 0 };
+
+      *word = (w << 1 | lower) ^ (w >> 1 | upper);
+    }
+
+    let mut result = Self { words, len: self.len };
+    result.clear_tail();
+    result
+  }
+
+  // Bit i of the result is bit i - 1, bit 0 is set to fill.
+  pub fn shifted_up(&self, fill: bool) -> Self {
+    let mut carry = fill as u64;
+    let mut words = Vec::with_capacity(self.words.len());
+
+    for w in &self.words {
+      words.push(w << 1 | carry);
+      carry = w >> (BITS - 1);
+    }
+
+    let mut result = Self { words, len: self.len };
+    result.clear_tail();
+    result
+  }
+
+  // Bit i of the result is bit i + 1, the last bit is set to fill.
+  pub fn shifted_down(&self, fill: bool) -> Self {
+    let mut result = self.clone();
+
+    for (i, w) in result.words.iter_mut().enumerate() {
+      let next = self.words.get(i + 1).cloned().unwrap_or(0);
+      *w = self.words[i] >> 1 | next << (BITS - 1);
+    }
+
+    if !result.is_empty() {
+      result.set(self.len - 1, fill);
+    }
+    result
+  }
+
+  // Combines three vectors of the same length word by word
+  pub fn combine<F>(a: &Self, b: &Self, c: &Self, f: F) -> Self
+  where F: Fn(u64, u64, u64) -> u64 {
+    let words = a.words.iter()
+      .zip(&b.words)
+      .zip(&c.words)
+      .map(|((a, b), c)| f(*a, *b, *c))
+      .collect::<Vec<_>>();
+
+    let mut result = Self { words, len: a.len };
+    result.clear_tail();
+    result
+  }
+
+  fn clear_tail(&mut self) {
+    let rest = self.len % BITS;
+    if rest != 0 {
+      if let Some(last) = self.words.last_mut() {
+        *last &= (1 << rest) - 1;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn to_string(bits: &BitVec) -> String {
+    (0..bits.len()).map(|i| if bits.get(i) { '1' } else { '0' }).collect::<String>()
+  }
+
+  fn sample(len: usize) -> String {
+    (0..len).map(|i| if (i * 7 + i / 3) % 5 < 2 { '1' } else { '0' }).collect::<String>()
+  }
+
+  #[test]
+  pub fn reverse_and_invert() -> Result<(), ParseError> {
+    for len in [0, 1, 5, 63, 64, 65, 130] {
+      let s = sample(len);
+      let mut bits = BitVec::parse(&s, '0', '1')?;
+      bits.reverse();
+      bits.invert();
+
+      let expected = s.chars().rev().map(|c| if c == '0' { '1' } else { '0' }).collect::<String>();
+      assert_eq!(expected, to_string(&bits));
+      assert_eq!(expected.matches('1').count(), bits.count_ones());
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  pub fn xor_neighbours() -> Result<(), ParseError> {
+    for len in [1, 2, 10, 64, 100, 129] {
+      let s = sample(len).chars().collect::<Vec<_>>();
+      let bits = BitVec::parse(&s.iter().collect::<String>(), '0', '1')?;
+
+      let expected = (0..len)
+        .map(|i| {
+          let left = i > 0 && s[i - 1] == '1';
+          let right = i + 1 < len && s[i + 1] == '1';
+          if left != right { '1' } else { '0' }
+        })
+        .collect::<String>();
+      assert_eq!(expected, to_string(&bits.xor_neighbours()));
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  pub fn shifted() -> Result<(), ParseError> {
+    for len in [1, 2, 64, 65, 100] {
+      let s = sample(len);
+      let bits = BitVec::parse(&s, '0', '1')?;
+
+      assert_eq!(format!("1{}", &s[..len - 1]), to_string(&bits.shifted_up(true)));
+      assert_eq!(format!("{}0", &s[1..]), to_string(&bits.shifted_down(false)));
+      assert_eq!(to_string(&bits.xor_neighbours()), to_string(&BitVec::combine(&bits.shifted_up(false), &bits, &bits.shifted_down(false), |l, _, r| l ^ r)));
+    }
+
+    Ok(())
+  }
+
+  #[test]
+  pub fn count_ones_before() -> Result<(), ParseError> {
+    let s = sample(200);
+    let bits = BitVec::parse(&s, '0', '1')?;
+
+    for n in 0..=200 {
+      assert_eq!(s[..n].matches('1').count(), bits.count_ones_before(n));
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,178 @@
+use super::Error;
+
+// Integer types the solver accepts. Everything is computed on u128 internally
+// and converted back at the end, so negative remainders are fine as long as
+// the moduli are positive.
+pub trait Residue: Copy {
+  fn to_u128_mod(self, modulus: u128) -> u128;
+  fn to_modulus(self) -> Option<u128>;
+  fn from_u128(v: u128) -> Option<Self>;
+}
+
+macro_rules! residue_unsigned {
+  ($($t:ty),*) => {$(
+    impl Residue for $t {
+      fn to_u128_mod(self, modulus: u128) -> u128 {
+        self as u128 % modulus
+      }
+
+      fn to_modulus(self) -> Option<u128> {
+        if self == 0 { None } else { Some(self as u128) }
+      }
+
+      fn from_u128(v: u128) -> Option<Self> {
+        Self::try_from(v).ok()
+      }
+    }
+  )*}
+}
+
+macro_rules! residue_signed {
+  ($($t:ty),*) => {$(
+    impl Residue for $t {
+      fn to_u128_mod(self, modulus: u128) -> u128 {
+        let r = self.unsigned_abs() as u128 % modulus;
+        if self < 0 && r != 0 { modulus - r } else { r }
+      }
+
+      fn to_modulus(self) -> Option<u128> {
+        if self <= 0 { None } else { Some(self as u128) }
+      }
+
+      fn from_u128(v: u128) -> Option<Self> {
+        Self::try_from(v).ok()
+      }
+    }
+  )*}
+}
+
+residue_unsigned!(u8, u16, u32, u64, u128, usize);
+residue_signed!(i8, i16, i32, i64, i128, isize);
+
+fn gcd(a: u128, b: u128) -> u128 {
+  if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// a * b % m without overflowing, falls back to double and add for large values
+fn mul_mod(a: u128, b: u128, m: u128) -> u128 {
+  let a = a % m;
+  let mut b = b % m;
+
+  if let Some(p) = a.checked_mul(b) {
+    return p % m;
+  }
+
+  let mut result = 0;
+  let mut a = a;
+  while b > 0 {
+    if b & 1 == 1 {
+      result = add_mod(result, a, m);
+    }
+    a = add_mod(a, a, m);
+    b >>= 1;
+  }
+
+  result
+}
+
+fn add_mod(a: u128, b: u128, m: u128) -> u128 {
+  if a >= m - b { a - (m - b) } else { a + b }
+}
+
+fn sub_mod(a: u128, b: u128, m: u128) -> u128 {
+  if a >= b { a - b } else { m - (b - a) }
+}
+
+// Inverse of a modulo m via the extended Euclidean algorithm. The Bézout
+// coefficient is tracked modulo m so it never leaves the unsigned range.
+fn mod_inverse(a: u128, m: u128) -> Option<u128> {
+  if m == 1 {
+    return Some(0);
+  }
+
+  let (mut old_r, mut r) = (a % m, m);
+  let (mut old_t, mut t) = (1, 0);
+
+  while r != 0 {
+    let q = old_r / r;
+    (old_r, r) = (r, old_r - q * r);
+    (old_t, t) = (t, sub_mod(old_t, mul_mod(q, t, m), m));
+  }
+
+  if old_r == 1 { Some(old_t) } else { None }
+}
+
+// Merges x = a1 (mod m1) and x = a2 (mod m2) into x = a (mod lcm(m1, m2)).
+fn merge(a1: u128, m1: u128, a2: u128, m2: u128) -> Result<(u128, u128), Error> {
+  let g = gcd(m1, m2);
+  let diff = sub_mod(a2 % m2, a1 % m2, m2);
+
+  if !diff.is_multiple_of(g) {
+    return Err(Error::new(&format!("Congruences x = {} (mod {}) and x = {} (mod {}) are inconsistent", a1, m1, a2, m2)));
+  }
+
+  let m = m2 / g;
+  let lcm = (m1 / g).checked_mul(m2).ok_or(Error::new("Combined modulus does not fit into 128 bits"))?;
+  let inv = mod_inverse(m1 / g % m, m).ok_or(Error::new("Could not invert modulus"))?;
+  let k = mul_mod(diff / g, inv, m);
+
+  Ok((add_mod(a1, mul_mod(k, m1, lcm), lcm), lcm))
+}
+
+// Solves the system of congruences x = remainder (mod modulus) given as
+// (remainder, modulus) pairs. The moduli do not need to be coprime.
+//
+// Returns the smallest non-negative solution and the period after which the
+// solutions repeat, i.e. the lcm of all moduli.
+pub fn crt<T: Residue>(congruences: &[(T, T)]) -> Result<(T, T), Error> {
+  let mut solution = 0;
+  let mut period = 1;
+
+  for (remainder, modulus) in congruences {
+    let modulus = modulus.to_modulus().ok_or(Error::new("Moduli must be positive"))?;
+    let remainder = remainder.to_u128_mod(modulus);
+
+    (solution, period) = merge(solution, period, remainder, modulus)?;
+  }
+
+  let too_large = || Error::new("Solution does not fit into the requested type");
+  Ok((T::from_u128(solution).ok_or_else(too_large)?, T::from_u128(period).ok_or_else(too_large)?))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  pub fn coprime() -> Result<(), Error> {
+    assert_eq!((23, 105), crt(&[(2, 3), (3, 5), (2, 7)])?);
+    Ok(())
+  }
+
+  #[test]
+  pub fn not_coprime() -> Result<(), Error> {
+    assert_eq!((10, 12), crt(&[(2i64, 4), (4, 6)])?);
+    assert!(crt(&[(1, 4), (2, 6)]).is_err());
+    Ok(())
+  }
+
+  #[test]
+  pub fn negative_remainders() -> Result<(), Error> {
+    assert_eq!((5, 6), crt(&[(-1i32, 2), (-1, 3)])?);
+    assert!(crt(&[(1i32, -2)]).is_err());
+    Ok(())
+  }
+
+  #[test]
+  pub fn large_moduli() -> Result<(), Error> {
+    let p = u128::MAX - 158; // largest 128 bit prime
+    let q = (1u128 << 61) - 1;
+    assert_eq!((p - 1, p), crt(&[(p - 1, p)])?);
+    assert!(crt(&[(p - 1, p), (12345, q)]).is_err());
+
+    let (x, period) = crt(&[(q - 1, q), (7, (1u128 << 64) + 1)])?;
+    assert_eq!(q * ((1u128 << 64) + 1), period);
+    assert_eq!((q - 1, 7), (x % q, x % ((1u128 << 64) + 1)));
+    Ok(())
+  }
+}
@@ -1,6 +1,11 @@
 use std::num::{ParseIntError, ParseFloatError};
 use std::fmt;
 
+pub mod bitvec;
+pub mod crt;
+pub mod rangeset;
+pub mod table;
+
 #[derive(Debug)]
 pub struct Error {
   pub what: String
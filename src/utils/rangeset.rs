@@ -0,0 +1,205 @@
+use std::fmt::Debug;
+
+// Unsigned integers a RangeSet can hold
+pub trait Integer: Copy + Ord + Debug {
+  fn succ(self) -> Option<Self>;
+  fn pred(self) -> Option<Self>;
+  // number of values in start..=self, minus one
+  fn distance(self, start: Self) -> u128;
+}
+
+macro_rules! integer {
+  ($($t:ty),*) => {$(
+    impl Integer for $t {
+      fn succ(self) -> Option<Self> {
+        self.checked_add(1)
+      }
+
+      fn pred(self) -> Option<Self> {
+        self.checked_sub(1)
+      }
+
+      fn distance(self, start: Self) -> u128 {
+        (self - start) as u128
+      }
+    }
+  )*}
+}
+
+integer!(u8, u16, u32, u64, u128, usize);
+
+// Set of integers stored as sorted, disjoint and non-adjacent inclusive
+// ranges. Inclusive ends make it possible to hold T::MAX.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeSet<T: Integer> {
+  ranges: Vec<(T, T)>,
+}
+
+impl<T: Integer> Default for RangeSet<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T: Integer> FromIterator<(T, T)> for RangeSet<T> {
+  fn from_iter<I: IntoIterator<Item = (T, T)>>(iter: I) -> Self {
+    let mut set = Self::new();
+    for (start, end) in iter {
+      set.insert(start, end);
+    }
+    set
+  }
+}
+
+impl<T: Integer> RangeSet<T> {
+  pub fn new() -> Self {
+    Self { ranges: vec![] }
+  }
+
+  // Adds start..=end, empty if end < start
+  pub fn insert(&mut self, start: T, end: T) {
+    if end < start {
+      return;
+    }
+
+    // first range that ends at or after start - 1, everything before it stays
+    let first = self.ranges.partition_point(|r| r.1.succ().map(|e| e < start).unwrap_or(false));
+    let mut last = first;
+    let mut start = start;
+    let mut end = end;
+
+    // merge everything that overlaps or touches the new range
+    while last < self.ranges.len() && end.succ().map(|e| self.ranges[last].0 <= e).unwrap_or(true) {
+      start = start.min(self.ranges[last].0);
+      end = end.max(self.ranges[last].1);
+      last += 1;
+    }
+
+    self.ranges.splice(first..last, [(start, end)]);
+  }
+
+  #[allow(dead_code)]
+  pub fn contains(&self, value: T) -> bool {
+    let i = self.ranges.partition_point(|r| r.1 < value);
+    self.ranges.get(i).map(|r| r.0 <= value).unwrap_or(false)
+  }
+
+  #[allow(dead_code)]
+  pub fn is_empty(&self) -> bool {
+    self.ranges.is_empty()
+  }
+
+  // Number of values in the set, None if it doesn't fit into u128
+  pub fn len(&self) -> Option<u128> {
+    self.ranges.iter()
+      .try_fold(0u128, |sum, r| sum.checked_add(r.1.distance(r.0))?.checked_add(1))
+  }
+
+  pub fn ranges(&self) -> impl Iterator<Item = (T, T)> + '_ {
+    self.ranges.iter().cloned()
+  }
+
+  // Lazily walks through every single value in the set in ascending order
+  pub fn values(&self) -> impl Iterator<Item = T> + '_ {
+    self.ranges.iter().flat_map(|&(start, end)| {
+      let mut next = Some(start);
+      std::iter::from_fn(move || {
+        let current = next?;
+        next = if current < end { current.succ() } else { None };
+        Some(current)
+      })
+    })
+  }
+
+  #[allow(dead_code)]
+  pub fn union(&self, other: &Self) -> Self {
+    self.ranges().chain(other.ranges()).collect()
+  }
+
+  #[allow(dead_code)]
+  pub fn intersection(&self, other: &Self) -> Self {
+    let mut ranges = vec![];
+    let (mut i, mut j) = (0, 0);
+
+    while i < self.ranges.len() && j < other.ranges.len() {
+      let a = self.ranges[i];
+      let b = other.ranges[j];
+
+      let start = a.0.max(b.0);
+      let end = a.1.min(b.1);
+      if start <= end {
+        ranges.push((start, end));
+      }
+
+      if a.1 < b.1 { i += 1 } else { j += 1 }
+    }
+
+    Self { ranges }
+  }
+
+  // Everything in start..=end that is not in the set
+  pub fn complement(&self, start: T, end: T) -> Self {
+    let mut ranges = vec![];
+    let mut next = Some(start);
+
+    for r in &self.ranges {
+      let from = match next {
+        Some(from) if from <= end => from,
+        _ => break,
+      };
+
+      if r.1 < from {
+        continue;
+      }
+
+      if from < r.0 {
+        if let Some(to) = r.0.pred() {
+          ranges.push((from, to.min(end)));
+        }
+      }
+      next = r.1.succ();
+    }
+
+    if let Some(from) = next {
+      if from <= end {
+        ranges.push((from, end));
+      }
+    }
+
+    Self { ranges }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  pub fn insert_merges() {
+    let set = [(5u32, 8), (0, 2), (4, 7), (10, 10), (3, 3)].into_iter().collect::<RangeSet<_>>();
+    assert_eq!(vec![(0, 8), (10, 10)], set.ranges().collect::<Vec<_>>());
+    assert_eq!(Some(10), set.len());
+    assert!(set.contains(3) && !set.contains(9) && set.contains(10) && !set.contains(11));
+  }
+
+  #[test]
+  pub fn complement() {
+    let set = [(5u32, 8), (0, 2)].into_iter().collect::<RangeSet<_>>();
+    assert_eq!(vec![3, 4, 9], set.complement(0, 9).values().collect::<Vec<_>>());
+    assert_eq!(vec![(3, 4)], set.complement(1, 6).ranges().collect::<Vec<_>>());
+    assert_eq!(Some(256), RangeSet::new().complement(0u8, 255).len());
+
+    let full = [(0u128, u128::MAX)].into_iter().collect::<RangeSet<_>>();
+    assert!(full.complement(0, u128::MAX).is_empty());
+    assert_eq!(None, full.len());
+  }
+
+  #[test]
+  pub fn union_and_intersection() {
+    let a = [(0u64, 10), (20, 30)].into_iter().collect::<RangeSet<_>>();
+    let b = [(5u64, 25), (40, 50)].into_iter().collect::<RangeSet<_>>();
+
+    assert_eq!(vec![(0, 30), (40, 50)], a.union(&b).ranges().collect::<Vec<_>>());
+    assert_eq!(vec![(5, 10), (20, 25)], a.intersection(&b).ranges().collect::<Vec<_>>());
+  }
+}
@@ -0,0 +1,97 @@
+use std::str::FromStr;
+use super::ParseError;
+
+// How values of a table are grouped into tuples. Row major reads the table
+// like text, column major reads each column of a block of rows downwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+  RowMajor,
+  ColumnMajor,
+}
+
+// Reads a table of whitespace separated values with exactly `columns` values
+// per line. Empty lines are skipped, errors name the line they occur in.
+pub fn parse_table<T: FromStr>(input: &str, columns: usize) -> Result<Vec<Vec<T>>, ParseError> {
+  input.lines()
+    .enumerate()
+    .filter(|(_, l)| !l.trim().is_empty())
+    .map(|(i, l)| {
+      let row = l.split_whitespace()
+        .map(|v| v.parse::<T>().map_err(|_| ParseError::new(&format!("Invalid number '{}' in line {}", v, i + 1))))
+        .collect::<Result<Vec<_>, ParseError>>()?;
+
+      if row.len() != columns {
+        Err(ParseError::new(&format!("Expected {} values in line {}, found {}", columns, i + 1, row.len())))?;
+      }
+
+      Ok(row)
+    })
+    .collect::<Result<Vec<_>, ParseError>>()
+}
+
+// Groups the values of a table into tuples of K values. Fails if the values
+// don't add up to full tuples instead of silently dropping the rest.
+pub fn group<T, R, const K: usize>(table: &[R], order: Order) -> Result<Vec<[T; K]>, ParseError>
+where T: Copy + Default, R: AsRef<[T]> {
+  if K == 0 {
+    Err(ParseError::new("Tuples must not be empty"))?;
+  }
+
+  let values: Vec<T> = match order {
+    Order::RowMajor => table.iter().flat_map(|r| r.as_ref().iter().cloned()).collect(),
+    Order::ColumnMajor => {
+      if !table.len().is_multiple_of(K) {
+        Err(ParseError::new(&format!("{} rows can't be grouped by {} in column major order", table.len(), K)))?;
+      }
+
+      let mut values = vec![];
+      for (b, block) in table.chunks(K).enumerate() {
+        let width = block[0].as_ref().len();
+        if let Some(r) = block.iter().position(|r| r.as_ref().len() != width) {
+          Err(ParseError::new(&format!("Row {} is not as wide as row {}", b * K + r + 1, b * K + 1)))?;
+        }
+
+        for column in 0..width {
+          values.extend(block.iter().map(|r| r.as_ref()[column]));
+        }
+      }
+      values
+    },
+  };
+
+  if !values.len().is_multiple_of(K) {
+    Err(ParseError::new(&format!("{} values can't be grouped by {}", values.len(), K)))?;
+  }
+
+  Ok(values.chunks(K)
+    .map(|c| {
+      let mut tuple = [T::default(); K];
+      tuple.copy_from_slice(c);
+      tuple
+    })
+    .collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  pub fn parse() {
+    assert_eq!(vec![vec![1u32, 2], vec![3, 4]], parse_table::<u32>("  1  2\n\n3 4\n", 2).unwrap());
+    assert_eq!("Expected 2 values in line 2, found 3", parse_table::<u32>("1 2\n3 4 5", 2).unwrap_err().what);
+    assert_eq!("Invalid number 'x' in line 1", parse_table::<u32>("1 x", 2).unwrap_err().what);
+    assert!(parse_table::<u8>("256", 1).is_err());
+  }
+
+  #[test]
+  pub fn grouping() -> Result<(), ParseError> {
+    let table = parse_table::<u32>("1 2\n3 4\n5 6\n7 8", 2)?;
+
+    assert_eq!(vec![[1, 2, 3, 4], [5, 6, 7, 8]], group::<_, _, 4>(&table, Order::RowMajor)?);
+    assert_eq!(vec![[1, 3], [2, 4], [5, 7], [6, 8]], group::<_, _, 2>(&table, Order::ColumnMajor)?);
+    assert!(group::<_, _, 3>(&table, Order::RowMajor).is_err());
+    assert!(group::<_, _, 3>(&table, Order::ColumnMajor).is_err());
+    Ok(())
+  }
+}